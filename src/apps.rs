@@ -1,34 +1,106 @@
+mod aria2;
+mod command;
 mod deluge;
+mod deluged;
+mod plex;
 mod qbittorrent;
+mod rtorrent;
+mod slskd;
+mod syncthing;
+mod transmission;
+mod webhook;
 
-use crate::LINE_FEED;
-use crate::error::Error::{ParsingFailure, PortPath};
+use crate::config::{Config, parse_var};
+use crate::error::Error::{AppResponse, Authorization, Binding, ParsingFailure};
 use crate::error::Result;
-use reqwest::blocking::Client;
+use crate::scgi::ScgiAddress;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
 use std::fmt::Debug;
-use std::path::Path;
 use std::str::FromStr;
-use std::thread::sleep;
 use std::time::Duration;
 use strum::{Display, EnumString};
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, error, info, warn};
 
 // Environment Variables
 const APPLICATION: &str = "APPLICATION";
 const PROTOCOL: &str = "PROTOCOL";
 const HOST: &str = "HOST";
-const PORT: &str = "PORT";
+pub const PORT: &str = "PORT";
 const USER: &str = "USER";
 const PASSWORD: &str = "PASSWORD";
-const PORT_FORWARD_PATH: &str = "PORT_FORWARD_PATH";
-const CHECK_INTERVAL: &str = "CHECK_INTERVAL";
+const API_KEY: &str = "API_KEY";
+pub const CHECK_INTERVAL: &str = "CHECK_INTERVAL";
+pub const RECONCILE_INTERVAL: &str = "RECONCILE_INTERVAL";
+pub const REANNOUNCE: &str = "REANNOUNCE";
+pub const REANNOUNCE_CATEGORY: &str = "REANNOUNCE_CATEGORY";
+pub const REANNOUNCE_TRACKER: &str = "REANNOUNCE_TRACKER";
+pub const BIND_INTERFACE: &str = "BIND_INTERFACE";
+pub const BIND_ADDRESS: &str = "BIND_ADDRESS";
+pub const BIND_CHECK_ONLY: &str = "BIND_CHECK_ONLY";
+pub const DISABLE_PORT_MAPPING: &str = "DISABLE_PORT_MAPPING";
+pub const PAUSE_AFTER: &str = "PAUSE_AFTER";
+const SCGI_ADDRESS: &str = "SCGI_ADDRESS";
+const DELUGE_DAEMON: &str = "DELUGE_DAEMON";
+const WEBHOOK_METHOD: &str = "WEBHOOK_METHOD";
+const WEBHOOK_URL: &str = "WEBHOOK_URL";
+const WEBHOOK_HEADERS: &str = "WEBHOOK_HEADERS";
+const WEBHOOK_BODY: &str = "WEBHOOK_BODY";
+const WEBHOOK_VERIFY_METHOD: &str = "WEBHOOK_VERIFY_METHOD";
+const WEBHOOK_VERIFY_URL: &str = "WEBHOOK_VERIFY_URL";
+const WEBHOOK_VERIFY_POINTER: &str = "WEBHOOK_VERIFY_POINTER";
+const COMMAND: &str = "COMMAND";
+const VERIFY_COMMAND: &str = "VERIFY_COMMAND";
+pub const COMMAND_TIMEOUT: &str = "COMMAND_TIMEOUT";
+
+/// Variables that can be set for each target
+pub const VARIABLES: &[&str] = &[
+    APPLICATION,
+    PROTOCOL,
+    HOST,
+    PORT,
+    USER,
+    PASSWORD,
+    API_KEY,
+    CHECK_INTERVAL,
+    RECONCILE_INTERVAL,
+    REANNOUNCE,
+    REANNOUNCE_CATEGORY,
+    REANNOUNCE_TRACKER,
+    BIND_INTERFACE,
+    BIND_ADDRESS,
+    BIND_CHECK_ONLY,
+    DISABLE_PORT_MAPPING,
+    PAUSE_AFTER,
+    SCGI_ADDRESS,
+    DELUGE_DAEMON,
+    WEBHOOK_METHOD,
+    WEBHOOK_URL,
+    WEBHOOK_HEADERS,
+    WEBHOOK_BODY,
+    WEBHOOK_VERIFY_METHOD,
+    WEBHOOK_VERIFY_URL,
+    WEBHOOK_VERIFY_POINTER,
+    COMMAND,
+    VERIFY_COMMAND,
+    COMMAND_TIMEOUT,
+];
+
+/// Variables that can also be read from the file in the `_FILE` variable, ie. `PASSWORD_FILE`
+pub const SECRETS: &[&str] = &[USER, PASSWORD, API_KEY, WEBHOOK_HEADERS];
+
+const PORT_PLACEHOLDER: &str = "{port}";
 
 // Defaults
 const HOST_DEFAULT: &str = "localhost";
 const USER_DEFAULT: &str = "admin";
 const PASSWORD_DEFAULT: &str = "";
-const PORT_FORWARD_PATH_DEFAULT: &str = "/tmp/gluetun/forwarded_port";
 const CHECK_INTERVAL_DEFAULT: u64 = 30;
+const WEBHOOK_METHOD_DEFAULT: &str = "POST";
+const WEBHOOK_VERIFY_METHOD_DEFAULT: &str = "GET";
+const COMMAND_TIMEOUT_DEFAULT: u64 = 60;
 
 pub trait App {
     /// Attempts to log in to host and returns error is unsuccessful
@@ -36,33 +108,135 @@ pub trait App {
 
     /// Attempts to set port value and returns error is unsuccessful
     fn set_port(&self, port: u16) -> Result<()>;
-    fn interval(&self) -> Duration;
-    fn port_forward_path(&self) -> &Path;
-    fn wait(&self) {
-        sleep(self.interval());
+
+    /// Returns the port the application is currently using, or none when it cannot be read.
+    /// The forwarded port is given for applications where the request is a template, ie. a webhook.
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        Ok(None)
+    }
+
+    /// Forces the torrents that match the filter to announce the new port to their trackers
+    fn reannounce(&self, _filter: &ReannounceFilter) -> Result<()> {
+        Err(AppResponse("Reannounce is not supported".into()))
     }
 
-    fn check_port_forward(&self) -> Result<u16> {
-        match self.port_forward_path().try_exists()? {
+    /// Returns the UPnP and NAT-PMP settings of the application that can compete with the forwarded port
+    fn port_mapping(&self) -> Result<Vec<(&'static str, bool)>> {
+        Ok(Vec::new())
+    }
+
+    /// Pauses the active torrents and returns the ones it paused, torrents that were already paused are not included
+    fn pause(&self) -> Result<Vec<String>> {
+        Err(AppResponse("Pausing torrents is not supported".into()))
+    }
+
+    /// Resumes the torrents returned by `pause`
+    fn resume(&self, _torrents: &[String]) -> Result<()> {
+        Err(AppResponse("Resuming torrents is not supported".into()))
+    }
+
+    /// Makes sure the application only uses the VPN interface, or reports it in check only mode
+    fn bind_interface(&self, _binding: &InterfaceBinding) -> Result<()> {
+        Err(AppResponse("Interface binding is not supported".into()))
+    }
+
+    fn interval(&self) -> Duration;
+}
+
+/// VPN interface the application has to be bound to so traffic cannot leave outside of the tunnel
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct InterfaceBinding {
+    /// Interface name, ie. `tun0` or `wg0`
+    pub interface: String,
+    /// Address of the interface when the application should only use that address
+    pub address: Option<String>,
+    /// Only report a missing binding instead of setting it
+    pub check_only: bool,
+}
+
+impl InterfaceBinding {
+    /// Compares the settings read with `get` to the expected settings and sets them with `set` when they differ
+    fn enforce(
+        &self,
+        settings: Value,
+        get: impl Fn() -> Result<Value>,
+        set: impl Fn(&Value) -> Result<()>,
+    ) -> Result<()> {
+        let mismatches = |actual: Value| -> Vec<String> {
+            settings
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter(|(key, value)| actual.get(key.as_str()) != Some(value))
+                .map(|(key, value)| {
+                    format!(
+                        "{key} is {} instead of {value}",
+                        actual.get(key.as_str()).unwrap_or(&Value::Null)
+                    )
+                })
+                .collect()
+        };
+
+        let found = mismatches(get()?);
+        if found.is_empty() {
+            debug!("Bound to interface {}", self.interface);
+            return Ok(());
+        }
+        if self.check_only {
+            return Err(Binding(found.join(", ")));
+        }
+        warn!(
+            "Binding to interface {} -> {}",
+            self.interface,
+            found.join(", ")
+        );
+        set(&settings)?;
+        let found = mismatches(get()?);
+        match found.is_empty() {
             true => {
-                let value = std::fs::read_to_string(self.port_forward_path())?;
-                trace!("Found port value {}", value);
-                let value = value.trim_matches(LINE_FEED);
-                Ok(value.parse::<u16>()?)
+                info!("Bound to interface {}", self.interface);
+                Ok(())
             }
-            false => Err(PortPath(format!(
-                "Path {} does not exist",
-                self.port_forward_path().display()
-            ))),
+            false => Err(Binding(found.join(", "))),
         }
     }
 }
 
+/// Torrents to reannounce, every torrent when neither is set
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ReannounceFilter {
+    /// qBittorrent category or Deluge label
+    pub category: Option<String>,
+    /// Part of the tracker URL or host, ie. `tracker.example.com`
+    pub tracker: Option<String>,
+}
+
+impl ReannounceFilter {
+    pub fn is_empty(&self) -> bool {
+        self.category.is_none() && self.tracker.is_none()
+    }
+
+    fn matches_tracker(&self, tracker: &str) -> bool {
+        self.tracker
+            .as_deref()
+            .is_none_or(|filter| tracker.contains(filter))
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, Copy, Display, EnumString)]
 #[strum(ascii_case_insensitive)]
 pub enum Application {
     QBittorrent,
     Deluge,
+    Transmission,
+    RTorrent,
+    Aria2,
+    Deluged,
+    Webhook,
+    Command,
+    Syncthing,
+    Slskd,
+    Plex,
 }
 
 #[derive(Debug, Eq, PartialEq, Default, Clone, Copy, Display, EnumString)]
@@ -73,49 +247,97 @@ pub enum Protocol {
     Https,
 }
 
+/// Transport used to reach an application's API
+pub enum Connection {
+    /// HTTP requests to the URL built by [`endpoint`]
+    Http(Client),
+    /// SCGI requests to a unix socket or TCP address
+    Scgi(ScgiAddress),
+}
+
 impl Application {
     pub fn default_port(&self) -> u16 {
         match self {
             Application::QBittorrent => 8080,
             Application::Deluge => 8112,
+            Application::Transmission => 9091,
+            Application::RTorrent => 80,
+            Application::Aria2 => 6800,
+            Application::Deluged => 58846,
+            Application::Webhook => 80,
+            Application::Command => 0,
+            Application::Syncthing => 8384,
+            Application::Slskd => 5030,
+            Application::Plex => 32400,
         }
     }
+
+    /// Returns true when the application implements `App::reannounce`
+    pub fn supports_reannounce(&self) -> bool {
+        matches!(
+            self,
+            Application::QBittorrent | Application::Deluge | Application::Deluged
+        )
+    }
+
+    /// Returns true when the application implements `App::pause` and `App::resume`
+    pub fn supports_pause(&self) -> bool {
+        matches!(
+            self,
+            Application::QBittorrent | Application::Deluge | Application::Deluged
+        )
+    }
+
+    /// Returns true when the application implements `App::bind_interface`
+    pub fn supports_binding(&self) -> bool {
+        matches!(
+            self,
+            Application::QBittorrent | Application::Deluge | Application::Deluged
+        )
+    }
+}
+
+/// Returns the application type of the target with the prefix
+pub fn application(config: &Config, prefix: &str) -> Result<Application> {
+    Application::from_str(
+        config
+            .target_var(prefix, APPLICATION)
+            .unwrap_or_default()
+            .as_str(),
+    )
+    .map_err(|_| ParsingFailure(format!("{APPLICATION} value is not valid application type")))
 }
 
-pub fn app_init() -> Result<Box<dyn App>> {
+/// Builds the application from the variables with the prefix, ie. `QBIT_` for the `qbit` target, UPnP and NAT-PMP are
+/// turned off with each port update when `disable_port_mapping` is set
+pub fn app_init(config: &Config, prefix: &str, disable_port_mapping: bool) -> Result<Box<dyn App>> {
     let client = Client::builder().cookie_store(true).build()?;
-    let application = Application::from_str(
-        std::env::var(APPLICATION).unwrap_or_default().as_str(),
+    let application = application(config, prefix)?;
+    let protocol = Protocol::from_str(
+        config
+            .target_var(prefix, PROTOCOL)
+            .unwrap_or_default()
+            .as_str(),
     )
-    .map_err(|_| ParsingFailure(format!("{APPLICATION} value is not valid application type")))?;
-    let protocol = Protocol::from_str(std::env::var(PROTOCOL).unwrap_or_default().as_str())
-        .unwrap_or_default();
-    let port = match std::env::var(PORT) {
-        Ok(value) => value.parse::<u16>().unwrap_or_else(|error| {
-            warn!(
-                "Using default value, could not parse: {} -> {}",
-                value, error
-            );
-            application.default_port()
-        }),
+    .unwrap_or_default();
+    let port = match config.target_var(prefix, PORT) {
+        Ok(value) => parse_var(PORT, value.as_str())?,
         _ => application.default_port(),
     };
-    let interval = Duration::from_secs(match std::env::var(CHECK_INTERVAL) {
-        Ok(value) => value.parse::<u64>().unwrap_or_else(|error| {
-            warn!(
-                "Using default value, could not parse: {} -> {}",
-                value, error
-            );
-            CHECK_INTERVAL_DEFAULT
-        }),
+    let interval = Duration::from_secs(match config.target_var(prefix, CHECK_INTERVAL) {
+        Ok(value) => parse_var(CHECK_INTERVAL, value.as_str())?,
         _ => CHECK_INTERVAL_DEFAULT,
     });
-    let hostname = std::env::var(HOST).unwrap_or(HOST_DEFAULT.into());
-    let username = std::env::var(USER).unwrap_or(USER_DEFAULT.into());
-    let password = std::env::var(PASSWORD).unwrap_or(PASSWORD_DEFAULT.into());
-    let port_forward_path = std::env::var(PORT_FORWARD_PATH)
-        .unwrap_or(PORT_FORWARD_PATH_DEFAULT.into())
-        .into();
+    let hostname = config
+        .target_var(prefix, HOST)
+        .unwrap_or(HOST_DEFAULT.into());
+    let username = config
+        .target_secret(prefix, USER)?
+        .unwrap_or(USER_DEFAULT.into());
+    let password = config
+        .target_secret(prefix, PASSWORD)?
+        .unwrap_or(PASSWORD_DEFAULT.into());
+    let api_key = config.target_secret(prefix, API_KEY)?.unwrap_or_default();
 
     // Print selected values
     debug!("application: {}", application);
@@ -124,7 +346,6 @@ pub fn app_init() -> Result<Box<dyn App>> {
     debug!("interval: {:?}", interval);
     debug!("hostname: {}", hostname);
     debug!("username: {}", username);
-    debug!("port_forward_path: {:?}", port_forward_path);
 
     Ok(match application {
         Application::QBittorrent => Box::new(qbittorrent::Qbittorrent {
@@ -134,7 +355,7 @@ pub fn app_init() -> Result<Box<dyn App>> {
             hostname,
             username,
             password,
-            port_forward_path,
+            disable_port_mapping,
             interval,
         }),
         Application::Deluge => Box::new(deluge::Deluge {
@@ -143,7 +364,132 @@ pub fn app_init() -> Result<Box<dyn App>> {
             port,
             hostname,
             password,
-            port_forward_path,
+            daemon: config.target_var(prefix, DELUGE_DAEMON).ok(),
+            disable_port_mapping,
+            interval,
+        }),
+        Application::Transmission => Box::new(transmission::Transmission {
+            client,
+            protocol,
+            port,
+            hostname,
+            username,
+            password,
+            interval,
+            session_id: RefCell::new(None),
+        }),
+        Application::RTorrent => Box::new(rtorrent::RTorrent {
+            connection: match config.target_var(prefix, SCGI_ADDRESS) {
+                Ok(value) => Connection::Scgi(ScgiAddress::from_str(value.as_str())?),
+                Err(_) => Connection::Http(client),
+            },
+            protocol,
+            port,
+            hostname,
+            username,
+            password,
+            interval,
+        }),
+        Application::Aria2 => Box::new(aria2::Aria2 {
+            client,
+            protocol,
+            port,
+            hostname,
+            secret: password,
+            interval,
+        }),
+        Application::Deluged => Box::new(deluged::Deluged {
+            port,
+            hostname,
+            username,
+            password,
+            disable_port_mapping,
+            interval,
+            stream: RefCell::new(None),
+            request_id: Cell::new(0),
+        }),
+        Application::Webhook => Box::new(webhook::Webhook {
+            client,
+            protocol,
+            port,
+            hostname,
+            method: webhook::parse_method(
+                config
+                    .target_var(prefix, WEBHOOK_METHOD)
+                    .unwrap_or(WEBHOOK_METHOD_DEFAULT.into())
+                    .as_str(),
+            )?,
+            url: config
+                .target_var(prefix, WEBHOOK_URL)
+                .map_err(|_| ParsingFailure(format!("{WEBHOOK_URL} is required for webhook")))?,
+            headers: webhook::parse_headers(
+                config
+                    .target_secret(prefix, WEBHOOK_HEADERS)?
+                    .unwrap_or_default()
+                    .as_str(),
+            )?,
+            body: config.target_var(prefix, WEBHOOK_BODY).ok(),
+            verify: match config.target_var(prefix, WEBHOOK_VERIFY_URL) {
+                Ok(url) => Some(webhook::WebhookVerify {
+                    method: webhook::parse_method(
+                        config
+                            .target_var(prefix, WEBHOOK_VERIFY_METHOD)
+                            .unwrap_or(WEBHOOK_VERIFY_METHOD_DEFAULT.into())
+                            .as_str(),
+                    )?,
+                    url,
+                    pointer: config
+                        .target_var(prefix, WEBHOOK_VERIFY_POINTER)
+                        .map_err(|_| {
+                            ParsingFailure(format!(
+                                "{WEBHOOK_VERIFY_POINTER} is required with {WEBHOOK_VERIFY_URL}"
+                            ))
+                        })?,
+                }),
+                Err(_) => None,
+            },
+            interval,
+        }),
+        Application::Command => Box::new(command::Command {
+            command: config
+                .target_var(prefix, COMMAND)
+                .map_err(|_| ParsingFailure(format!("{COMMAND} is required for command")))?
+                .parse()?,
+            verify: match config.target_var(prefix, VERIFY_COMMAND) {
+                Ok(value) => Some(value.parse()?),
+                Err(_) => None,
+            },
+            timeout: Duration::from_secs(match config.target_var(prefix, COMMAND_TIMEOUT) {
+                Ok(value) => parse_var(COMMAND_TIMEOUT, value.as_str())?,
+                Err(_) => COMMAND_TIMEOUT_DEFAULT,
+            }),
+            interval,
+        }),
+        Application::Syncthing => Box::new(syncthing::Syncthing {
+            client,
+            protocol,
+            port,
+            hostname,
+            api_key,
+            interval,
+        }),
+        Application::Slskd => Box::new(slskd::Slskd {
+            client,
+            protocol,
+            port,
+            hostname,
+            username,
+            password,
+            api_key,
+            token: Default::default(),
+            interval,
+        }),
+        Application::Plex => Box::new(plex::Plex {
+            client,
+            protocol,
+            port,
+            hostname,
+            token: api_key,
             interval,
         }),
     })
@@ -153,6 +499,35 @@ fn endpoint(protocol: Protocol, hostname: &str, port: u16, endpoint: &str) -> St
     format!("{}://{}:{}{}", protocol, hostname, port, endpoint)
 }
 
+/// Returns the port of a single port range, or 0 so a range of several ports is never taken as the forwarded port
+fn single_port((start, end): (u16, u16)) -> u16 {
+    match start == end {
+        true => start,
+        false => 0,
+    }
+}
+
+/// Replaces every `{port}` in the template with the port value
+fn substitute_port(template: &str, port: u16) -> String {
+    template.replace(PORT_PLACEHOLDER, port.to_string().as_str())
+}
+
+/// Returns the response when it was successful, or an authorization error when it was rejected
+pub fn check_status(name: &str, response: Response) -> Result<Response> {
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        debug!("{name} request failed with status code: {}", status);
+        Err(Authorization)
+    } else if status.is_success() {
+        Ok(response)
+    } else {
+        Err(AppResponse(format!(
+            "{name} request failed with status code: {}",
+            status
+        )))
+    }
+}
+
 pub fn result_to_bool(result: Result<()>) -> bool {
     match result {
         Ok(_) => true,
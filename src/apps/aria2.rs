@@ -0,0 +1,177 @@
+use crate::apps::{App, Protocol, endpoint};
+use crate::error::Error::{AppResponse, Authorization, ParsingFailure, PortUpdate};
+use crate::error::Result;
+use crate::rpc::{JsonRpcVersion, RpcRequest, RpcResponse, generate_id};
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{debug, info, trace};
+
+const ARIA2_ENDPOINT: &str = "/jsonrpc";
+const GET_VERSION_METHOD: &str = "aria2.getVersion";
+const GET_GLOBAL_OPTION_METHOD: &str = "aria2.getGlobalOption";
+const LISTEN_PORT_OPTION: &str = "listen-port";
+const TOKEN_PREFIX: &str = "token:";
+const UNAUTHORIZED_MESSAGE: &str = "Unauthorized";
+
+pub struct Aria2 {
+    pub client: Client,
+    pub protocol: Protocol,
+    pub hostname: String,
+    pub port: u16,
+    pub secret: String,
+    pub interval: Duration,
+}
+
+impl App for Aria2 {
+    fn login(&self) -> Result<()> {
+        let version = self.call(GET_VERSION_METHOD, vec![])?;
+        debug!(
+            "aria2 login successful with version {}",
+            version
+                .get("version")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+        );
+        Ok(())
+    }
+
+    /// aria2 can not change `listen-port` at runtime so the port is only verified, it has to be set at startup
+    fn set_port(&self, port: u16) -> Result<()> {
+        let actual_port = self.get_current_listen_port()?;
+        debug!("actual_port: {:?}", actual_port);
+        if port == actual_port {
+            info!("Port {} matches aria2 {LISTEN_PORT_OPTION}", port);
+            Ok(())
+        } else {
+            Err(PortUpdate(format!(
+                "aria2 {LISTEN_PORT_OPTION} {} does not match port {}, aria2 has to be restarted with --{LISTEN_PORT_OPTION}={}",
+                actual_port, port, port
+            )))
+        }
+    }
+
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        Ok(Some(self.get_current_listen_port()?))
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Aria2 {
+    fn endpoint(&self) -> String {
+        endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            ARIA2_ENDPOINT,
+        )
+    }
+
+    fn get_current_listen_port(&self) -> Result<u16> {
+        let options = self.call(GET_GLOBAL_OPTION_METHOD, vec![])?;
+        // aria2 reports every option value as a string
+        Ok(options
+            .get(LISTEN_PORT_OPTION)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ParsingFailure(format!("{LISTEN_PORT_OPTION} option is not a string")))?
+            .parse::<u16>()?)
+    }
+
+    /// Sends the method with the secret token prepended to the parameters when one is configured
+    fn call(&self, method: &str, params: Vec<Value>) -> Result<Value> {
+        let params = match self.secret.is_empty() {
+            true => params,
+            false => std::iter::once(Value::String(format!("{TOKEN_PREFIX}{}", self.secret)))
+                .chain(params)
+                .collect(),
+        };
+        let request = RpcRequest::new(
+            JsonRpcVersion::V2,
+            method,
+            Value::Array(params),
+            generate_id(),
+        );
+        let response = self.send_rpc_request(&request)?;
+        trace!("{method} response: {:?}", response);
+
+        match response.error() {
+            None if response.is_success() => Ok(response.result().clone()),
+            Some(error) if error.message() == UNAUTHORIZED_MESSAGE => {
+                debug!("aria2 {method} was rejected with code {}", error.code());
+                Err(Authorization)
+            }
+            Some(error) => Err(AppResponse(format!(
+                "aria2 {method} with message -> {}",
+                error.message()
+            ))),
+            None => Err(AppResponse(format!("aria2 {method}"))),
+        }
+    }
+
+    fn send_rpc_request(&self, request: &RpcRequest) -> Result<RpcResponse> {
+        let client = &self.client;
+        let response = client.post(self.endpoint()).json(&request).send()?;
+        RpcResponse::try_from(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::POST;
+    use serde_json::json;
+
+    const SECRET: &str = "somesecret";
+
+    fn aria2(server: &MockServer) -> Aria2 {
+        Aria2 {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            secret: SECRET.to_string(),
+            interval: Default::default(),
+        }
+    }
+
+    #[test]
+    fn set_port() {
+        let server = MockServer::start();
+        let get = server.mock(|when, then| {
+            when.method(POST).path(ARIA2_ENDPOINT).json_body_includes(
+                r#"{"method":"aria2.getGlobalOption","params":["token:somesecret"]}"#,
+            );
+            then.status(200).json_body(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": {"listen-port": "6881", "dht-listen-port": "6881"}
+            }));
+        });
+
+        let app = aria2(&server);
+        assert!(app.set_port(6881).is_ok());
+        let error = app.set_port(6882).unwrap_err();
+        assert!(matches!(error, PortUpdate(_)));
+        assert!(error.to_string().contains("--listen-port=6882"));
+        get.assert_calls(2);
+    }
+
+    #[test]
+    fn login_unauthorized() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path(ARIA2_ENDPOINT);
+            then.status(400).json_body(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": {"code": 1, "message": "Unauthorized"}
+            }));
+        });
+
+        assert!(matches!(aria2(&server).login(), Err(Authorization)));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::apps::{App, substitute_port};
+use crate::error::Error::{AppResponse, ParsingFailure, PortUpdate};
+use crate::error::Result;
+use std::io::Read;
+use std::process::{Command as Process, Output, Stdio};
+use std::thread::{JoinHandle, sleep, spawn};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, trace, warn};
+
+/// Environment variable containing the forwarded port for the executed commands
+const FORWARDED_PORT: &str = "FORWARDED_PORT";
+/// Time between checks whether the program exited
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Program and arguments where `{port}` is replaced in each argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLine {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+/// Runs a configured program to update the port
+pub struct Command {
+    pub command: CommandLine,
+    pub verify: Option<CommandLine>,
+    /// Time a program can run before it is killed so it does not block the other targets
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+impl App for Command {
+    fn login(&self) -> Result<()> {
+        debug!("Command does not require login");
+        Ok(())
+    }
+
+    fn set_port(&self, port: u16) -> Result<()> {
+        let output = self.command.run(port, self.timeout)?;
+        trace!(
+            "Command stdout: {}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+
+        match self.current_port(port)? {
+            Some(actual_port) => {
+                debug!("actual_port: {:?}", actual_port);
+                if port == actual_port {
+                    info!("Port updated to {}", port);
+                    Ok(())
+                } else {
+                    Err(PortUpdate(format!(
+                        "Actual port {} does not match expected port number {}",
+                        actual_port, port
+                    )))
+                }
+            }
+            None => {
+                info!("Port updated to {}", port);
+                Ok(())
+            }
+        }
+    }
+
+    fn current_port(&self, port: u16) -> Result<Option<u16>> {
+        let Some(verify) = &self.verify else {
+            return Ok(None);
+        };
+        let output = verify.run(port, self.timeout)?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let actual_port = stdout.trim().parse::<u16>().map_err(|e| {
+            ParsingFailure(format!(
+                "Verify command output {} is not a port -> {e}",
+                stdout.trim()
+            ))
+        })?;
+        Ok(Some(actual_port))
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl CommandLine {
+    /// Runs the program and returns an error if it does not exit successfully, or it is killed after the timeout
+    fn run(&self, port: u16, timeout: Duration) -> Result<Output> {
+        let mut child = Process::new(self.program.as_str())
+            .args(self.args.iter().map(|arg| substitute_port(arg, port)))
+            .env(FORWARDED_PORT, port.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Read while the program runs so it does not block on a full pipe
+        let stdout = read_to_end(child.stdout.take());
+        let stderr = read_to_end(child.stderr.take());
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                return Err(AppResponse(format!(
+                    "{} was killed after {} seconds",
+                    self.program,
+                    timeout.as_secs()
+                )));
+            }
+            sleep(POLL_INTERVAL);
+        };
+        let output = Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        };
+        if output.status.success() {
+            Ok(output)
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!("{} stderr: {}", self.program, stderr.trim());
+            Err(AppResponse(format!(
+                "{} exited with {}",
+                self.program, output.status
+            )))
+        }
+    }
+}
+
+fn read_to_end(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+impl std::str::FromStr for CommandLine {
+    type Err = crate::error::Error;
+
+    /// Splits on whitespace outside of single or double quotes
+    fn from_str(s: &str) -> Result<Self> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut quote = None;
+        for c in s.chars() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), c) => word.get_or_insert_default().push(c),
+                (None, '\'' | '"') => {
+                    quote = Some(c);
+                    word.get_or_insert_default();
+                }
+                (None, c) if c.is_whitespace() => words.extend(word.take()),
+                (None, c) => word.get_or_insert_default().push(c),
+            }
+        }
+        if quote.is_some() {
+            return Err(ParsingFailure(format!("Command {s} has an unclosed quote")));
+        }
+        words.extend(word);
+
+        let mut words = words.into_iter();
+        match words.next() {
+            Some(program) => Ok(Self {
+                program,
+                args: words.collect(),
+            }),
+            None => Err(ParsingFailure("Command is empty".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn command_line() {
+        assert_eq!(
+            CommandLine::from_str(r#"sh -c 'echo "$1" > /tmp/port' sh {port}"#).unwrap(),
+            CommandLine {
+                program: "sh".into(),
+                args: vec![
+                    "-c".into(),
+                    r#"echo "$1" > /tmp/port"#.into(),
+                    "sh".into(),
+                    "{port}".into()
+                ]
+            }
+        );
+        assert!(CommandLine::from_str("  ").is_err());
+        assert!(CommandLine::from_str("echo 'port").is_err());
+    }
+
+    #[test]
+    fn set_port() {
+        let app = Command {
+            command: CommandLine::from_str(
+                r#"sh -c 'test "$1" = "$FORWARDED_PORT" && test "$1" != 6881' sh {port}"#,
+            )
+            .unwrap(),
+            verify: Some(CommandLine::from_str("echo 51413").unwrap()),
+            timeout: Duration::from_secs(10),
+            interval: Default::default(),
+        };
+        assert!(app.set_port(51413).is_ok());
+        assert!(matches!(app.set_port(51414), Err(PortUpdate(_))));
+        assert!(matches!(app.set_port(6881), Err(AppResponse(_))));
+    }
+
+    #[test]
+    fn kill_after_timeout() {
+        let app = Command {
+            command: CommandLine::from_str("sleep 10").unwrap(),
+            verify: None,
+            timeout: Duration::from_millis(200),
+            interval: Default::default(),
+        };
+        let start = Instant::now();
+        let error = app.set_port(51413).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(error.to_string().contains("sleep was killed"));
+    }
+}
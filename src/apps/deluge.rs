@@ -1,19 +1,41 @@
-use crate::apps::{App, Protocol, endpoint};
-use crate::error::Error::{AppResponse, Authorization};
+use crate::apps::{App, InterfaceBinding, Protocol, ReannounceFilter, endpoint, single_port};
+use crate::error::Error::{AppResponse, Authorization, ParsingFailure, PortUpdate, Session};
 use crate::error::Result;
-use crate::rpc::{JsonRpcVersion, RpcId, RpcRequest, RpcResponse};
+use crate::rpc::{JsonRpcVersion, RpcRequest, RpcResponse, generate_id};
 use reqwest::blocking::Client;
 use serde_json::{Value, json};
-use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tracing::{debug, warn};
+use std::time::Duration;
+use tracing::{debug, info, warn};
 
 const AUTH_METHOD: &str = "auth.login";
 const GET_HOSTS_METHOD: &str = "web.get_hosts";
 const CONNECT_METHOD: &str = "web.connect";
+const DISCONNECT_METHOD: &str = "web.disconnect";
 const CONNECTED_METHOD: &str = "web.connected";
+const GET_HOST_STATUS_METHOD: &str = "web.get_host_status";
+const OFFLINE_STATUS: &str = "Offline";
+const CONNECTED_STATUS: &str = "Connected";
 const SET_CONFIG_METHOD: &str = "core.set_config";
+const GET_CONFIG_VALUE_METHOD: &str = "core.get_config_value";
+const GET_CONFIG_VALUES_METHOD: &str = "core.get_config_values";
+const GET_TORRENTS_STATUS_METHOD: &str = "core.get_torrents_status";
+const FORCE_REANNOUNCE_METHOD: &str = "core.force_reannounce";
+const PAUSE_SESSION_METHOD: &str = "core.pause_session";
+const RESUME_SESSION_METHOD: &str = "core.resume_session";
+const IS_SESSION_PAUSED_METHOD: &str = "core.is_session_paused";
+const LISTEN_PORTS: &str = "listen_ports";
+const LABEL: &str = "label";
+const TRACKER_HOST: &str = "tracker_host";
+const STATE: &str = "state";
+const PAUSED_STATES: [&str; 2] = ["Paused", "Error"];
+const LISTEN_INTERFACE: &str = "listen_interface";
+const OUTGOING_INTERFACE: &str = "outgoing_interface";
+const UPNP: &str = "upnp";
+const NATPMP: &str = "natpmp";
+const RANDOM_PORT: &str = "random_port";
 const DELUGE_ENDPOINT: &str = "/json";
+/// Error code of a request without a valid session cookie, ie. after deluge-web restarted
+const NOT_AUTHENTICATED_CODE: i64 = 1;
 
 #[allow(unused)]
 #[derive(Debug)]
@@ -24,39 +46,42 @@ struct DelugeHostResponse {
     username: String,
 }
 
+impl DelugeHostResponse {
+    /// Matches the host ID, host, port or `host:port`
+    fn matches(&self, daemon: &str) -> bool {
+        daemon == self.id
+            || daemon == self.host
+            || daemon == self.port.to_string()
+            || daemon == format!("{}:{}", self.host, self.port)
+    }
+}
+
 pub struct Deluge {
     pub client: Client,
     pub protocol: Protocol,
     pub hostname: String,
     pub port: u16,
     pub password: String,
-    pub port_forward_path: PathBuf,
+    /// Host ID, host, port or `host:port` of the daemon to connect to, the first online daemon when not set
+    pub daemon: Option<String>,
+    /// Turns off UPnP and NAT-PMP with each port update
+    pub disable_port_mapping: bool,
     pub interval: Duration,
 }
 
 impl App for Deluge {
     fn login(&self) -> Result<()> {
-        let connected = self.is_connected()?;
+        let connected = match self.is_connected() {
+            Err(Session(_)) => false,
+            result => result?,
+        };
         if !connected && self.authorize()? {
             debug!("Deluge {} method success", AUTH_METHOD);
-            let hosts = self.get_hosts()?;
-            match hosts.first() {
-                None => {
-                    warn!("Could not get Deluge Host ID");
-                    Err(Authorization)
-                }
-                Some(host) => {
-                    debug!("Deluge {} method success", GET_HOSTS_METHOD);
-                    self.connect(host.id.as_str())?;
-                    Ok(())
-                }
-            }
+            let host = self.select_host(self.get_hosts()?)?;
+            self.connect(host.id.as_str())
         } else {
             match connected {
-                true => {
-                    debug!("Deluge is already connected");
-                    Ok(())
-                }
+                true => self.check_connected_daemon(),
                 false => Err(Authorization),
             }
         }
@@ -66,23 +91,74 @@ impl App for Deluge {
         let request = RpcRequest::new(
             JsonRpcVersion::V1,
             SET_CONFIG_METHOD,
-            json!([{"listen_ports": [port, port]}]),
+            json!([port_config(port, self.disable_port_mapping)]),
             generate_id(),
         );
         let response = self.send_rpc_request(&request)?;
-        if response.is_success() {
+        if !response.is_success() {
+            return Err(AppResponse(format!("Deluge {SET_CONFIG_METHOD}")));
+        }
+
+        // Deluge ignores listen_ports while random_port is enabled
+        let random_port = self.get_config_value(RANDOM_PORT)?;
+        debug!("{RANDOM_PORT}: {}", random_port);
+        if random_port.as_bool().unwrap_or_default() {
+            return Err(PortUpdate(format!(
+                "Deluge {RANDOM_PORT} is enabled so {LISTEN_PORTS} is not used"
+            )));
+        }
+        let actual_port = self.get_current_listen_port()?;
+        debug!("actual_port: {:?}", actual_port);
+        if port == actual_port {
+            info!("Port updated to {}", port);
             Ok(())
         } else {
-            Err(AppResponse(format!("Deluge {SET_CONFIG_METHOD}")))
+            Err(PortUpdate(format!(
+                "Actual port {} does not match expected port number {}",
+                actual_port, port
+            )))
         }
     }
 
-    fn interval(&self) -> Duration {
-        self.interval
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        Ok(Some(self.get_current_listen_port()?))
+    }
+
+    fn reannounce(&self, filter: &ReannounceFilter) -> Result<()> {
+        let statuses = self.call(GET_TORRENTS_STATUS_METHOD, torrents_status_params(filter))?;
+        let ids = torrent_ids(&statuses, filter)?;
+        if ids.is_empty() {
+            debug!("No torrents match {:?}", filter);
+            return Ok(());
+        }
+        self.call(FORCE_REANNOUNCE_METHOD, json!([ids]))?;
+        info!("Reannounced {} torrents", ids.len());
+        Ok(())
+    }
+
+    fn port_mapping(&self) -> Result<Vec<(&'static str, bool)>> {
+        port_mapping_values(&self.call(GET_CONFIG_VALUES_METHOD, port_mapping_params())?)
     }
 
-    fn port_forward_path(&self) -> &Path {
-        self.port_forward_path.as_path()
+    fn pause(&self) -> Result<Vec<String>> {
+        pause_session(|method, params| self.call(method, params))
+    }
+
+    fn resume(&self, torrents: &[String]) -> Result<()> {
+        resume_session(torrents, |method, params| self.call(method, params))
+    }
+
+    fn bind_interface(&self, binding: &InterfaceBinding) -> Result<()> {
+        let settings = interface_settings(binding);
+        binding.enforce(
+            settings.clone(),
+            || self.call(GET_CONFIG_VALUES_METHOD, config_keys(&settings)),
+            |settings| self.call(SET_CONFIG_METHOD, json!([settings])).map(drop),
+        )
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
     }
 }
 
@@ -135,6 +211,94 @@ impl Deluge {
         }
     }
 
+    /// Switches to the selected daemon when deluge-web is connected to another one, ie. after `daemon` changed
+    fn check_connected_daemon(&self) -> Result<()> {
+        let Some(daemon) = &self.daemon else {
+            debug!("Deluge is already connected");
+            return Ok(());
+        };
+        let hosts = self.get_hosts()?;
+        for host in hosts.iter().filter(|host| host.matches(daemon)) {
+            if self.get_host_status(host.id.as_str())? == CONNECTED_STATUS {
+                debug!("Deluge is already connected to {}:{}", host.host, host.port);
+                return Ok(());
+            }
+        }
+        warn!("Deluge is connected to another daemon, switching to {daemon}");
+        self.call(DISCONNECT_METHOD, json!([]))?;
+        let host = self.select_host(hosts)?;
+        self.connect(host.id.as_str())
+    }
+
+    /// Returns the first online daemon that matches `daemon`
+    fn select_host(&self, hosts: Vec<DelugeHostResponse>) -> Result<DelugeHostResponse> {
+        let available = hosts
+            .iter()
+            .map(|host| format!("{}:{} ({})", host.host, host.port, host.id))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let candidates: Vec<_> = hosts
+            .into_iter()
+            .filter(|host| {
+                self.daemon
+                    .as_deref()
+                    .is_none_or(|daemon| host.matches(daemon))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Err(AppResponse(match &self.daemon {
+                Some(daemon) => {
+                    format!("No Deluge daemon matches {daemon}, available: {available}")
+                }
+                None => "Deluge web does not have any daemons".into(),
+            }));
+        }
+
+        for host in candidates {
+            let status = self.get_host_status(host.id.as_str())?;
+            debug!(
+                "Deluge daemon {}:{} status: {}",
+                host.host, host.port, status
+            );
+            if status == OFFLINE_STATUS {
+                warn!("Skipping offline Deluge daemon {}:{}", host.host, host.port);
+            } else {
+                return Ok(host);
+            }
+        }
+        Err(AppResponse(format!(
+            "No matching Deluge daemon is online, available: {available}"
+        )))
+    }
+
+    /// Returns the status of the daemon, ie. `Online`, `Offline` or `Connected`
+    fn get_host_status(&self, host_id: &str) -> Result<String> {
+        let request = RpcRequest::new(
+            JsonRpcVersion::V1,
+            GET_HOST_STATUS_METHOD,
+            json!([host_id]),
+            generate_id(),
+        );
+        let response = self.send_rpc_request(&request)?;
+        if let Some(error) = response.error() {
+            return Err(AppResponse(format!(
+                "Deluge {GET_HOST_STATUS_METHOD} with message -> {}",
+                error.message()
+            )));
+        }
+        response
+            .result()
+            .get(1)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| {
+                ParsingFailure(format!(
+                    "Deluge {GET_HOST_STATUS_METHOD} result is not valid: {}",
+                    response.result()
+                ))
+            })
+    }
+
     fn connect(&self, host_id: &str) -> Result<()> {
         let request = RpcRequest::new(
             JsonRpcVersion::V1,
@@ -225,18 +389,411 @@ impl Deluge {
         }
     }
 
+    fn get_config_value(&self, key: &str) -> Result<Value> {
+        self.call(GET_CONFIG_VALUE_METHOD, json!([key]))
+    }
+
+    /// Sends the request and returns its result or the error message
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = RpcRequest::new(JsonRpcVersion::V1, method, params, generate_id());
+        let response = self.send_rpc_request(&request)?;
+        if let Some(error) = response.error() {
+            Err(AppResponse(format!(
+                "Deluge {method} with message -> {}",
+                error.message()
+            )))
+        } else {
+            Ok(response.result().clone())
+        }
+    }
+
+    /// Returns the listen port, or 0 when `listen_ports` is a range of several ports
+    fn get_current_listen_port(&self) -> Result<u16> {
+        let listen_ports = self.get_config_value(LISTEN_PORTS)?;
+        debug!("{LISTEN_PORTS}: {}", listen_ports);
+        let port = |index: usize| {
+            listen_ports
+                .get(index)
+                .and_then(Value::as_u64)
+                .and_then(|port| u16::try_from(port).ok())
+                .ok_or_else(|| ParsingFailure(format!("{listen_ports} is not a port range")))
+        };
+        Ok(single_port((port(0)?, port(1)?)))
+    }
+
     fn send_rpc_request(&self, request: &RpcRequest) -> Result<RpcResponse> {
         let client = &self.client;
         let response = client.post(self.endpoint()).json(&request).send()?;
-        RpcResponse::try_from(response)
+        let response = RpcResponse::try_from(response)?;
+        match response.error() {
+            Some(error) if error.code() == NOT_AUTHENTICATED_CODE => Err(Session(format!(
+                "Deluge {} with message -> {}",
+                request.method(),
+                error.message()
+            ))),
+            _ => Ok(response),
+        }
+    }
+}
+
+/// Pauses the session when it has active torrents and returns them, nothing is paused when the session already was.
+/// Torrents that were paused on their own stay paused when the session is resumed.
+pub fn pause_session(call: impl Fn(&str, Value) -> Result<Value>) -> Result<Vec<String>> {
+    if call(IS_SESSION_PAUSED_METHOD, json!([]))?.as_bool() == Some(true) {
+        debug!("Session is already paused");
+        return Ok(Vec::new());
     }
+    let statuses = call(GET_TORRENTS_STATUS_METHOD, json!([{}, [STATE]]))?;
+    let ids: Vec<String> = statuses
+        .as_object()
+        .ok_or_else(|| {
+            ParsingFailure(format!(
+                "{GET_TORRENTS_STATUS_METHOD} result is not an object"
+            ))
+        })?
+        .iter()
+        .filter(|(_, status)| {
+            let state = status
+                .get(STATE)
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            !PAUSED_STATES.contains(&state)
+        })
+        .map(|(id, _)| id.clone())
+        .collect();
+    if !ids.is_empty() {
+        call(PAUSE_SESSION_METHOD, json!([]))?;
+    }
+    Ok(ids)
+}
+
+/// Resumes the session paused by `pause_session`
+pub fn resume_session(
+    torrents: &[String],
+    call: impl Fn(&str, Value) -> Result<Value>,
+) -> Result<()> {
+    if !torrents.is_empty() {
+        call(RESUME_SESSION_METHOD, json!([]))?;
+    }
+    Ok(())
+}
+
+/// `core.set_config` settings for the port, with UPnP and NAT-PMP turned off when `disable_port_mapping` is set
+pub fn port_config(port: u16, disable_port_mapping: bool) -> Value {
+    let mut config = json!({LISTEN_PORTS: [port, port]});
+    if disable_port_mapping {
+        config[UPNP] = json!(false);
+        config[NATPMP] = json!(false);
+    }
+    config
+}
+
+/// Parameters of `core.get_config_values` for the UPnP and NAT-PMP settings
+pub fn port_mapping_params() -> Value {
+    json!([[UPNP, NATPMP]])
 }
 
-fn generate_id() -> RpcId {
-    RpcId::Number(
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("SystemTime duration_since error")
-            .as_nanos(),
-    )
+/// Returns the UPnP and NAT-PMP settings from the `core.get_config_values` result
+pub fn port_mapping_values(values: &Value) -> Result<Vec<(&'static str, bool)>> {
+    [UPNP, NATPMP]
+        .into_iter()
+        .map(|key| {
+            values
+                .get(key)
+                .and_then(Value::as_bool)
+                .map(|value| (key, value))
+                .ok_or_else(|| ParsingFailure(format!("Deluge {key} value is not a boolean")))
+        })
+        .collect()
+}
+
+/// Deluge listens on the interface address, or the interface name when there is no address, and sends from the interface
+pub fn interface_settings(binding: &InterfaceBinding) -> Value {
+    json!({
+        LISTEN_INTERFACE: binding.address.as_deref().unwrap_or(binding.interface.as_str()),
+        OUTGOING_INTERFACE: binding.interface,
+    })
+}
+
+/// Parameters of `core.get_config_values` for the keys of the settings
+pub fn config_keys(settings: &Value) -> Value {
+    json!([settings
+        .as_object()
+        .map(|settings| settings.keys().collect::<Vec<_>>())
+        .unwrap_or_default()])
+}
+
+/// Parameters of `core.get_torrents_status` for the torrents with the label and their tracker host
+pub fn torrents_status_params(filter: &ReannounceFilter) -> Value {
+    let filter_dict = match &filter.category {
+        Some(label) => json!({LABEL: label}),
+        None => json!({}),
+    };
+    json!([filter_dict, [TRACKER_HOST]])
+}
+
+/// Returns the IDs of the torrents in the `core.get_torrents_status` result with a matching tracker host
+pub fn torrent_ids(statuses: &Value, filter: &ReannounceFilter) -> Result<Vec<String>> {
+    Ok(statuses
+        .as_object()
+        .ok_or_else(|| {
+            ParsingFailure(format!(
+                "{GET_TORRENTS_STATUS_METHOD} result is not an object"
+            ))
+        })?
+        .iter()
+        .filter(|(_, status)| {
+            filter.matches_tracker(
+                status
+                    .get(TRACKER_HOST)
+                    .and_then(Value::as_str)
+                    .unwrap_or_default(),
+            )
+        })
+        .map(|(id, _)| id.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::POST;
+    use std::cell::RefCell;
+
+    fn deluge(server: &MockServer) -> Deluge {
+        Deluge {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            password: Default::default(),
+            daemon: None,
+            disable_port_mapping: false,
+            interval: Default::default(),
+        }
+    }
+
+    fn config_value(server: &MockServer, key: &str, value: Value) {
+        server.mock(|when, then| {
+            when.method(POST).path(DELUGE_ENDPOINT).json_body_includes(
+                json!({"method": GET_CONFIG_VALUE_METHOD, "params": [key]}).to_string(),
+            );
+            then.status(200)
+                .json_body(json!({"id": 1, "result": value, "error": null}));
+        });
+    }
+
+    #[test]
+    fn set_port() {
+        let server = MockServer::start();
+        let set = server.mock(|when, then| {
+            when.method(POST)
+                .path(DELUGE_ENDPOINT)
+                .json_body_includes(r#"{"method":"core.set_config"}"#);
+            then.status(200)
+                .json_body(json!({"id": 1, "result": null, "error": null}));
+        });
+        config_value(&server, RANDOM_PORT, json!(false));
+        config_value(&server, LISTEN_PORTS, json!([6881, 6881]));
+        let app = deluge(&server);
+
+        assert!(app.set_port(6881).is_ok());
+        assert!(matches!(app.set_port(6882), Err(PortUpdate(_))));
+        set.assert_calls(2);
+    }
+
+    #[test]
+    fn random_port_enabled() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST)
+                .path(DELUGE_ENDPOINT)
+                .json_body_includes(r#"{"method":"core.set_config"}"#);
+            then.status(200)
+                .json_body(json!({"id": 1, "result": null, "error": null}));
+        });
+        config_value(&server, RANDOM_PORT, json!(true));
+        config_value(&server, LISTEN_PORTS, json!([6881, 6881]));
+
+        assert!(matches!(deluge(&server).set_port(6881), Err(PortUpdate(_))));
+    }
+
+    #[test]
+    fn select_online_daemon() {
+        let server = MockServer::start();
+        let status = |id: &str, status: &str| {
+            server.mock(|when, then| {
+                when.method(POST).path(DELUGE_ENDPOINT).json_body_includes(
+                    json!({"method": GET_HOST_STATUS_METHOD, "params": [id]}).to_string(),
+                );
+                then.status(200)
+                    .json_body(json!({"id": 1, "result": [id, status, "2.1.1"], "error": null}));
+            });
+        };
+        status("a1", OFFLINE_STATUS);
+        status("b2", "Online");
+        let hosts = || {
+            vec![
+                DelugeHostResponse {
+                    id: "a1".into(),
+                    host: "deluged-a".into(),
+                    port: 58846,
+                    username: "".into(),
+                },
+                DelugeHostResponse {
+                    id: "b2".into(),
+                    host: "deluged-b".into(),
+                    port: 58846,
+                    username: "".into(),
+                },
+            ]
+        };
+        let mut app = deluge(&server);
+        assert_eq!(app.select_host(hosts()).unwrap().id, "b2");
+
+        app.daemon = Some("deluged-b:58846".into());
+        assert_eq!(app.select_host(hosts()).unwrap().id, "b2");
+
+        app.daemon = Some("a1".into());
+        assert!(matches!(app.select_host(hosts()), Err(AppResponse(_))));
+
+        app.daemon = Some("deluged-c".into());
+        let error = app.select_host(hosts()).unwrap_err().to_string();
+        assert!(error.contains("No Deluge daemon matches deluged-c"));
+    }
+
+    #[test]
+    fn switch_connected_daemon() {
+        let server = MockServer::start();
+        let method = |method: &str, result: Value| {
+            server.mock(|when, then| {
+                when.method(POST)
+                    .path(DELUGE_ENDPOINT)
+                    .json_body_includes(json!({"method": method}).to_string());
+                then.status(200)
+                    .json_body(json!({"id": 1, "result": result, "error": null}));
+            })
+        };
+        let status = |id: &str, status: &str| {
+            server.mock(|when, then| {
+                when.method(POST).path(DELUGE_ENDPOINT).json_body_includes(
+                    json!({"method": GET_HOST_STATUS_METHOD, "params": [id]}).to_string(),
+                );
+                then.status(200)
+                    .json_body(json!({"id": 1, "result": [id, status, "2.1.1"], "error": null}));
+            });
+        };
+        method(CONNECTED_METHOD, json!(true));
+        method(
+            GET_HOSTS_METHOD,
+            json!([
+                ["a1", "deluged-a", 58846, ""],
+                ["b2", "deluged-b", 58846, ""]
+            ]),
+        );
+        status("a1", CONNECTED_STATUS);
+        status("b2", "Online");
+        let disconnect = method(DISCONNECT_METHOD, json!(true));
+        let connect = server.mock(|when, then| {
+            when.method(POST).path(DELUGE_ENDPOINT).json_body_includes(
+                json!({"method": CONNECT_METHOD, "params": ["b2"]}).to_string(),
+            );
+            then.status(200)
+                .json_body(json!({"id": 1, "result": [], "error": null}));
+        });
+
+        let mut app = deluge(&server);
+        app.daemon = Some("deluged-a".into());
+        assert!(app.login().is_ok());
+        disconnect.assert_calls(0);
+
+        app.daemon = Some("deluged-b".into());
+        assert!(app.login().is_ok());
+        disconnect.assert();
+        connect.assert();
+    }
+
+    #[test]
+    fn reannounce_label() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path(DELUGE_ENDPOINT).json_body_includes(
+                json!({
+                    "method": GET_TORRENTS_STATUS_METHOD,
+                    "params": [{"label": "tv"}, [TRACKER_HOST]]
+                })
+                .to_string(),
+            );
+            then.status(200).json_body(json!({
+                "id": 1,
+                "result": {
+                    "aaa": {TRACKER_HOST: "tracker.example.com"},
+                    "bbb": {TRACKER_HOST: "other.example.org"}
+                },
+                "error": null
+            }));
+        });
+        let reannounce = server.mock(|when, then| {
+            when.method(POST).path(DELUGE_ENDPOINT).json_body_includes(
+                json!({"method": FORCE_REANNOUNCE_METHOD, "params": [["aaa"]]}).to_string(),
+            );
+            then.status(200)
+                .json_body(json!({"id": 1, "result": null, "error": null}));
+        });
+        let filter = ReannounceFilter {
+            category: Some("tv".into()),
+            tracker: Some("tracker.example.com".into()),
+        };
+
+        assert!(deluge(&server).reannounce(&filter).is_ok());
+        reannounce.assert();
+    }
+
+    #[test]
+    fn port_mapping() {
+        assert_eq!(
+            port_config(6881, true),
+            json!({LISTEN_PORTS: [6881, 6881], UPNP: false, NATPMP: false})
+        );
+        assert_eq!(
+            port_config(6881, false),
+            json!({LISTEN_PORTS: [6881, 6881]})
+        );
+        assert_eq!(
+            port_mapping_values(&json!({UPNP: true, NATPMP: false})).unwrap(),
+            vec![(UPNP, true), (NATPMP, false)]
+        );
+        assert!(port_mapping_values(&json!({UPNP: true})).is_err());
+    }
+
+    #[test]
+    fn pause_and_resume_session() {
+        let calls = RefCell::new(Vec::new());
+        let call = |method: &str, _params: Value| {
+            calls.borrow_mut().push(method.to_string());
+            Ok(match method {
+                IS_SESSION_PAUSED_METHOD => json!(false),
+                GET_TORRENTS_STATUS_METHOD => json!({
+                    "aaa": {STATE: "Seeding"},
+                    "bbb": {STATE: "Paused"}
+                }),
+                _ => Value::Null,
+            })
+        };
+
+        let torrents = pause_session(call).unwrap();
+        assert_eq!(torrents, vec!["aaa"]);
+        resume_session(&torrents, call).unwrap();
+        resume_session(&[], call).unwrap();
+        assert_eq!(
+            *calls.borrow(),
+            vec![
+                IS_SESSION_PAUSED_METHOD,
+                GET_TORRENTS_STATUS_METHOD,
+                PAUSE_SESSION_METHOD,
+                RESUME_SESSION_METHOD
+            ]
+        );
+    }
 }
@@ -0,0 +1,391 @@
+use crate::apps::deluge::{
+    config_keys, interface_settings, pause_session, port_config, port_mapping_params,
+    port_mapping_values, resume_session, torrent_ids, torrents_status_params,
+};
+use crate::apps::{App, InterfaceBinding, ReannounceFilter, single_port};
+use crate::error::Error::{AppResponse, Authorization, IO, ParsingFailure, PortUpdate, Session};
+use crate::error::Result;
+use crate::rencode;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned};
+use serde_json::{Value, json};
+use std::cell::{Cell, RefCell};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, trace};
+
+const LOGIN_METHOD: &str = "daemon.login";
+const SET_CONFIG_METHOD: &str = "core.set_config";
+const GET_CONFIG_VALUE_METHOD: &str = "core.get_config_value";
+const GET_CONFIG_VALUES_METHOD: &str = "core.get_config_values";
+const GET_TORRENTS_STATUS_METHOD: &str = "core.get_torrents_status";
+const FORCE_REANNOUNCE_METHOD: &str = "core.force_reannounce";
+const CLIENT_VERSION: &str = "2.1.1";
+/// Version byte of the `!BI` message header used by released Deluge 2.x daemons
+const PROTOCOL_VERSION: u8 = 1;
+const RPC_RESPONSE: u64 = 1;
+const RPC_ERROR: u64 = 2;
+const RPC_EVENT: u64 = 3;
+const AUTHORIZATION_ERRORS: [&str; 2] = ["BadLoginError", "NotAuthorizedError"];
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+/// Connects directly to the Deluge daemon RPC port instead of the deluge-web JSON endpoint
+pub struct Deluged {
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    /// Turns off UPnP and NAT-PMP with each port update
+    pub disable_port_mapping: bool,
+    pub interval: Duration,
+    pub stream: RefCell<Option<TlsStream>>,
+    pub request_id: Cell<u64>,
+}
+
+impl App for Deluged {
+    fn login(&self) -> Result<()> {
+        self.stream.replace(Some(self.connect()?));
+        let auth_level = self.call(
+            LOGIN_METHOD,
+            json!([self.username, self.password]),
+            json!({"client_version": CLIENT_VERSION}),
+        )?;
+        debug!("Deluge daemon login successful with auth level {auth_level}");
+        Ok(())
+    }
+
+    fn set_port(&self, port: u16) -> Result<()> {
+        self.call(
+            SET_CONFIG_METHOD,
+            json!([port_config(port, self.disable_port_mapping)]),
+            json!({}),
+        )?;
+        let listen_ports =
+            self.call(GET_CONFIG_VALUE_METHOD, json!(["listen_ports"]), json!({}))?;
+        debug!("listen_ports: {}", listen_ports);
+        if listen_ports == json!([port, port]) {
+            info!("Port updated to {}", port);
+            Ok(())
+        } else {
+            Err(PortUpdate(format!(
+                "Actual listen ports {} do not match expected port number {}",
+                listen_ports, port
+            )))
+        }
+    }
+
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        let listen_ports =
+            self.call(GET_CONFIG_VALUE_METHOD, json!(["listen_ports"]), json!({}))?;
+        let port = |index: usize| {
+            listen_ports
+                .get(index)
+                .and_then(Value::as_u64)
+                .and_then(|port| u16::try_from(port).ok())
+                .ok_or_else(|| ParsingFailure(format!("{listen_ports} is not a port range")))
+        };
+        Ok(Some(single_port((port(0)?, port(1)?))))
+    }
+
+    fn reannounce(&self, filter: &ReannounceFilter) -> Result<()> {
+        let statuses = self.call(
+            GET_TORRENTS_STATUS_METHOD,
+            torrents_status_params(filter),
+            json!({}),
+        )?;
+        let ids = torrent_ids(&statuses, filter)?;
+        if ids.is_empty() {
+            debug!("No torrents match {:?}", filter);
+            return Ok(());
+        }
+        self.call(FORCE_REANNOUNCE_METHOD, json!([ids]), json!({}))?;
+        info!("Reannounced {} torrents", ids.len());
+        Ok(())
+    }
+
+    fn port_mapping(&self) -> Result<Vec<(&'static str, bool)>> {
+        port_mapping_values(&self.call(
+            GET_CONFIG_VALUES_METHOD,
+            port_mapping_params(),
+            json!({}),
+        )?)
+    }
+
+    fn pause(&self) -> Result<Vec<String>> {
+        pause_session(|method, args| self.call(method, args, json!({})))
+    }
+
+    fn resume(&self, torrents: &[String]) -> Result<()> {
+        resume_session(torrents, |method, args| self.call(method, args, json!({})))
+    }
+
+    fn bind_interface(&self, binding: &InterfaceBinding) -> Result<()> {
+        let settings = interface_settings(binding);
+        binding.enforce(
+            settings.clone(),
+            || self.call(GET_CONFIG_VALUES_METHOD, config_keys(&settings), json!({})),
+            |settings| {
+                self.call(SET_CONFIG_METHOD, json!([settings]), json!({}))
+                    .map(drop)
+            },
+        )
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Deluged {
+    fn connect(&self) -> Result<TlsStream> {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        // deluged generates a self-signed certificate so it cannot be verified
+        let config = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(self.hostname.clone()).map_err(|e| {
+            ParsingFailure(format!("{} is not a valid hostname -> {e}", self.hostname))
+        })?;
+        let connection = ClientConnection::new(Arc::new(config), server_name)?;
+
+        let socket = TcpStream::connect((self.hostname.as_str(), self.port))?;
+        socket.set_read_timeout(Some(TIMEOUT))?;
+        socket.set_write_timeout(Some(TIMEOUT))?;
+        debug!(
+            "Connected to Deluge daemon at {}:{}",
+            self.hostname, self.port
+        );
+        Ok(StreamOwned::new(connection, socket))
+    }
+
+    /// Sends a request and waits for its response, the connection is dropped if the exchange fails
+    fn call(&self, method: &str, args: Value, kwargs: Value) -> Result<Value> {
+        let mut stream = self.stream.borrow_mut();
+        let connection = stream
+            .as_mut()
+            .ok_or_else(|| Session(format!("Deluge daemon is not connected for {method}")))?;
+        let request_id = self.request_id.get();
+        self.request_id.set(request_id.wrapping_add(1));
+
+        let result = exchange(connection, request_id, method, args, kwargs);
+        if matches!(result, Err(IO(_))) {
+            stream.take();
+        }
+        result
+    }
+}
+
+fn exchange<S: Read + Write>(
+    stream: &mut S,
+    request_id: u64,
+    method: &str,
+    args: Value,
+    kwargs: Value,
+) -> Result<Value> {
+    write_message(stream, &json!([[request_id, method, args, kwargs]]))?;
+    loop {
+        let message = read_message(stream)?;
+        trace!("Deluge daemon message: {}", message);
+        let message_type = message.get(0).and_then(|v| v.as_u64());
+        if message_type == Some(RPC_EVENT) {
+            continue;
+        }
+        if message.get(1).and_then(|v| v.as_u64()) != Some(request_id) {
+            debug!("Ignoring Deluge daemon response for another request");
+            continue;
+        }
+        return match message_type {
+            Some(RPC_RESPONSE) => Ok(message.get(2).cloned().unwrap_or_default()),
+            Some(RPC_ERROR) => {
+                let exception = message.get(2).and_then(|v| v.as_str()).unwrap_or_default();
+                if AUTHORIZATION_ERRORS.contains(&exception) {
+                    Err(Authorization)
+                } else {
+                    Err(AppResponse(format!(
+                        "Deluge daemon {method} raised {exception} with message -> {}",
+                        message.get(3).cloned().unwrap_or_default()
+                    )))
+                }
+            }
+            _ => Err(ParsingFailure(format!(
+                "Deluge daemon message type is not valid: {message}"
+            ))),
+        };
+    }
+}
+
+/// Writes the protocol version, payload length and zlib compressed rencode payload
+fn write_message<W: Write>(writer: &mut W, value: &Value) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(rencode::encode(value).as_slice())?;
+    let payload = encoder.finish()?;
+    let length = u32::try_from(payload.len())
+        .map_err(|_| ParsingFailure("Deluge daemon request is too large".into()))?;
+
+    let mut message = vec![PROTOCOL_VERSION];
+    message.extend_from_slice(&length.to_be_bytes());
+    message.extend_from_slice(payload.as_slice());
+    writer.write_all(message.as_slice())?;
+    Ok(writer.flush()?)
+}
+
+fn read_message<R: Read>(reader: &mut R) -> Result<Value> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if header[0] != PROTOCOL_VERSION {
+        return Err(ParsingFailure(format!(
+            "Deluge daemon protocol version {} is not supported",
+            header[0]
+        )));
+    }
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let mut payload = vec![0; length];
+    reader.read_exact(&mut payload)?;
+
+    let mut data = Vec::new();
+    ZlibDecoder::new(payload.as_slice()).read_to_end(&mut data)?;
+    rencode::decode(data.as_slice())
+}
+
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn message_round_trip() {
+        let value = json!([[1, LOGIN_METHOD, ["localclient", "secret"], {}]]);
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &value).unwrap();
+        assert_eq!(buffer[0], PROTOCOL_VERSION);
+        assert_eq!(read_message(&mut Cursor::new(buffer)).unwrap(), value);
+    }
+
+    #[test]
+    fn login_message_bytes() {
+        let value = json!([[1, LOGIN_METHOD, ["localclient", "secret"], {}]]);
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &value).unwrap();
+
+        // struct.pack('!BI', 1, len(payload)) followed by the zlib compressed rencode payload
+        let length = (buffer.len() - 5) as u32;
+        assert_eq!(
+            buffer[..5],
+            [&[1], length.to_be_bytes().as_slice()].concat()
+        );
+        let mut payload = Vec::new();
+        ZlibDecoder::new(&buffer[5..])
+            .read_to_end(&mut payload)
+            .unwrap();
+        let mut expected = vec![0xC1, 0xC4, 0x01, 0x8C];
+        expected.extend_from_slice(b"daemon.login");
+        expected.extend_from_slice(&[0xC2, 0x8B]);
+        expected.extend_from_slice(b"localclient");
+        expected.push(0x86);
+        expected.extend_from_slice(b"secret");
+        expected.push(0x66);
+        assert_eq!(payload, expected);
+    }
+
+    #[test]
+    fn exchange_skips_events() {
+        let mut responses = Vec::new();
+        write_message(&mut responses, &json!([RPC_EVENT, "TorrentAddedEvent", []])).unwrap();
+        write_message(
+            &mut responses,
+            &json!([RPC_ERROR, 7, "BadLoginError", [], {}, ""]),
+        )
+        .unwrap();
+        let mut stream = MockStream {
+            input: Cursor::new(responses),
+            output: Vec::new(),
+        };
+
+        let result = exchange(&mut stream, 7, LOGIN_METHOD, json!([]), json!({}));
+        assert!(matches!(result, Err(Authorization)));
+        assert_eq!(
+            read_message(&mut Cursor::new(stream.output)).unwrap(),
+            json!([[7, LOGIN_METHOD, [], {}]])
+        );
+    }
+
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::apps::{App, Protocol, check_status, endpoint};
+use crate::error::Error::PortUpdate;
+use crate::error::Result;
+use quick_xml::events::Event;
+use quick_xml::{Reader, XmlVersion};
+use reqwest::blocking::{Client, RequestBuilder};
+use std::time::Duration;
+use tracing::{debug, info, trace};
+
+const PLEX_PREFS_ENDPOINT: &str = "/:/prefs";
+const TOKEN_HEADER: &str = "X-Plex-Token";
+const SETTING_TAG: &str = "Setting";
+const MANUAL_PORT_MAPPING_MODE: &str = "ManualPortMappingMode";
+const MANUAL_PORT_MAPPING_PORT: &str = "ManualPortMappingPort";
+const ENABLED_VALUES: [&str; 2] = ["1", "true"];
+
+/// Sets the manual port used by Plex remote access
+pub struct Plex {
+    pub client: Client,
+    pub protocol: Protocol,
+    pub hostname: String,
+    pub port: u16,
+    pub token: String,
+    pub interval: Duration,
+}
+
+impl App for Plex {
+    fn login(&self) -> Result<()> {
+        self.get_prefs()?;
+        debug!("Plex login successful");
+        Ok(())
+    }
+
+    fn set_port(&self, port: u16) -> Result<()> {
+        check_status(
+            "Plex",
+            self.request(self.client.put(format!(
+                "{}?{MANUAL_PORT_MAPPING_MODE}=1&{MANUAL_PORT_MAPPING_PORT}={port}",
+                self.prefs_endpoint()
+            )))
+            .send()?,
+        )?;
+
+        let prefs = self.get_prefs()?;
+        let mode = setting_value(prefs.as_str(), MANUAL_PORT_MAPPING_MODE)?;
+        let actual_port = setting_value(prefs.as_str(), MANUAL_PORT_MAPPING_PORT)?;
+        debug!("{MANUAL_PORT_MAPPING_MODE}: {:?}", mode);
+        debug!("actual_port: {:?}", actual_port);
+        if !mode.is_some_and(|mode| ENABLED_VALUES.contains(&mode.as_str())) {
+            Err(PortUpdate(format!(
+                "{MANUAL_PORT_MAPPING_MODE} is not enabled"
+            )))
+        } else if actual_port.as_deref() == Some(port.to_string().as_str()) {
+            info!("Port updated to {}", port);
+            Ok(())
+        } else {
+            Err(PortUpdate(format!(
+                "Actual port {} does not match expected port number {}",
+                actual_port.unwrap_or_default(),
+                port
+            )))
+        }
+    }
+
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        let prefs = self.get_prefs()?;
+        let mode = setting_value(prefs.as_str(), MANUAL_PORT_MAPPING_MODE)?;
+        if !mode.is_some_and(|mode| ENABLED_VALUES.contains(&mode.as_str())) {
+            // The port is not used while manual port mapping is disabled
+            return Ok(Some(0));
+        }
+        Ok(Some(
+            setting_value(prefs.as_str(), MANUAL_PORT_MAPPING_PORT)?
+                .as_deref()
+                .unwrap_or("0")
+                .parse()?,
+        ))
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Plex {
+    fn prefs_endpoint(&self) -> String {
+        endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            PLEX_PREFS_ENDPOINT,
+        )
+    }
+
+    fn request(&self, builder: RequestBuilder) -> RequestBuilder {
+        builder.header(TOKEN_HEADER, self.token.as_str())
+    }
+
+    fn get_prefs(&self) -> Result<String> {
+        let response = check_status(
+            "Plex",
+            self.request(self.client.get(self.prefs_endpoint()))
+                .send()?,
+        )?;
+        let prefs = response.text()?;
+        trace!("Plex prefs: {}", prefs);
+        Ok(prefs)
+    }
+}
+
+/// Returns the value attribute of the `<Setting>` element with the id
+fn setting_value(xml: &str, id: &str) -> Result<Option<String>> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(tag) | Event::Empty(tag) if tag.name().as_ref() == SETTING_TAG => {
+                let tag_id = tag
+                    .try_get_attribute("id")
+                    .map_err(quick_xml::Error::from)?;
+                if tag_id.is_some_and(|tag_id| tag_id.value.as_ref() == id) {
+                    return tag
+                        .try_get_attribute("value")
+                        .map_err(quick_xml::Error::from)?
+                        .map(|value| {
+                            Ok(value
+                                .normalized_value(XmlVersion::Implicit1_0)?
+                                .into_owned())
+                        })
+                        .transpose();
+                }
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::{GET, PUT};
+
+    const PREFS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MediaContainer size="3">
+<Setting id="FriendlyName" label="Friendly name" type="text" default="" value="plex &amp; vpn" />
+<Setting id="ManualPortMappingMode" label="Manually specify public port" type="bool" default="false" value="true" />
+<Setting id="ManualPortMappingPort" label="" type="int" default="32400" value="51413" />
+</MediaContainer>"#;
+
+    #[test]
+    fn setting() {
+        assert_eq!(
+            setting_value(PREFS, "FriendlyName").unwrap().as_deref(),
+            Some("plex & vpn")
+        );
+        assert_eq!(
+            setting_value(PREFS, MANUAL_PORT_MAPPING_PORT)
+                .unwrap()
+                .as_deref(),
+            Some("51413")
+        );
+        assert!(setting_value(PREFS, "Missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn set_port() {
+        const TOKEN: &str = "sometoken";
+        let server = MockServer::start();
+        let put = server.mock(|when, then| {
+            when.method(PUT)
+                .path(PLEX_PREFS_ENDPOINT)
+                .header(TOKEN_HEADER, TOKEN)
+                .query_param(MANUAL_PORT_MAPPING_MODE, "1")
+                .query_param_exists(MANUAL_PORT_MAPPING_PORT);
+            then.status(200);
+        });
+        let get = server.mock(|when, then| {
+            when.method(GET)
+                .path(PLEX_PREFS_ENDPOINT)
+                .header(TOKEN_HEADER, TOKEN);
+            then.status(200).body(PREFS);
+        });
+
+        let app = Plex {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            token: TOKEN.to_string(),
+            interval: Default::default(),
+        };
+        assert!(app.set_port(51413).is_ok());
+        assert!(matches!(app.set_port(51414), Err(PortUpdate(_))));
+        put.assert_calls(2);
+        get.assert_calls(2);
+    }
+}
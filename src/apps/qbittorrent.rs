@@ -1,10 +1,10 @@
-use crate::apps::{App, Protocol, endpoint};
-use crate::error::Error::{AppResponse, Authorization, ParsingFailure, PortUpdate};
+use crate::apps::{App, InterfaceBinding, Protocol, ReannounceFilter, endpoint};
+use crate::error::Error::{AppResponse, Authorization, ParsingFailure, PortUpdate, Session};
 use crate::error::Result;
 use reqwest::blocking::Client;
-use serde_json::Value;
+use reqwest::{StatusCode, Url};
+use serde_json::{Value, json};
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tracing::{debug, info, trace};
 
@@ -12,6 +12,24 @@ use tracing::{debug, info, trace};
 const QB_LOGIN_ENDPOINT: &str = "/api/v2/auth/login";
 const QB_SET_PREFERENCES_ENDPOINT: &str = "/api/v2/app/setPreferences";
 const QB_GET_PREFERENCES_ENDPOINT: &str = "/api/v2/app/preferences";
+const QB_TORRENTS_INFO_ENDPOINT: &str = "/api/v2/torrents/info";
+const QB_TORRENTS_TRACKERS_ENDPOINT: &str = "/api/v2/torrents/trackers";
+const QB_REANNOUNCE_ENDPOINT: &str = "/api/v2/torrents/reannounce";
+const QB_STOP_ENDPOINT: &str = "/api/v2/torrents/stop";
+const QB_START_ENDPOINT: &str = "/api/v2/torrents/start";
+// Before qBittorrent 5.0
+const QB_PAUSE_ENDPOINT: &str = "/api/v2/torrents/pause";
+const QB_RESUME_ENDPOINT: &str = "/api/v2/torrents/resume";
+
+const LISTEN_PORT: &str = "listen_port";
+const NETWORK_INTERFACE: &str = "current_network_interface";
+const INTERFACE_ADDRESS: &str = "current_interface_address";
+const UPNP: &str = "upnp";
+
+const ALL_HASHES: &str = "all";
+const HASH_SEPARATOR: &str = "|";
+/// Torrent state prefixes of stopped torrents, `paused` before qBittorrent 5.0
+const STOPPED_STATES: [&str; 2] = ["stopped", "paused"];
 
 pub struct Qbittorrent {
     pub client: Client,
@@ -20,7 +38,8 @@ pub struct Qbittorrent {
     pub port: u16,
     pub username: String,
     pub password: String,
-    pub port_forward_path: PathBuf,
+    /// Turns off UPnP and NAT-PMP with each port update
+    pub disable_port_mapping: bool,
     pub interval: Duration,
 }
 
@@ -48,39 +67,108 @@ impl App for Qbittorrent {
     }
 
     fn set_port(&self, port: u16) -> Result<()> {
-        let client = &self.client;
-        let json = HashMap::from([("json".to_string(), format!("{{\"listen_port\":{} }}", port))]);
-        let response = client
-            .post(self.set_preference_endpoint())
-            .form(&json)
+        let mut preferences = json!({LISTEN_PORT: port});
+        if self.disable_port_mapping {
+            preferences[UPNP] = json!(false);
+        }
+        self.set_preferences(preferences)?;
+        let actual_port = self.get_current_listen_port()?;
+        debug!("actual_port: {:?}", actual_port);
+        if port == actual_port {
+            info!("Port updated to {}", port);
+            Ok(())
+        } else {
+            Err(PortUpdate(format!(
+                "Actual port {} does not match expected port number {}",
+                actual_port, port
+            )))
+        }
+    }
+
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        Ok(Some(self.get_current_listen_port()?))
+    }
+
+    fn reannounce(&self, filter: &ReannounceFilter) -> Result<()> {
+        let hashes = match filter.is_empty() {
+            true => ALL_HASHES.to_string(),
+            false => {
+                let hashes = self.get_torrent_hashes(filter)?;
+                if hashes.is_empty() {
+                    debug!("No torrents match {:?}", filter);
+                    return Ok(());
+                }
+                hashes.join(HASH_SEPARATOR)
+            }
+        };
+        let response = self
+            .client
+            .post(self.reannounce_endpoint())
+            .form(&HashMap::from([("hashes", hashes)]))
             .send()?;
         let status = response.status();
-        if status.is_success() {
-            let actual_port = self.get_current_listen_port()?;
-            debug!("actual_port: {:?}", actual_port);
-            if port == actual_port {
-                info!("Port updated to {}", port);
-                Ok(())
-            } else {
-                Err(PortUpdate(format!(
-                    "Actual port {} does not match expected port number {}",
-                    actual_port, port
-                )))
-            }
+        if status == StatusCode::FORBIDDEN {
+            Err(Session(format!("qBittorrent reannounce status {status}")))
+        } else if status.is_success() {
+            info!("Reannounced torrents");
+            Ok(())
         } else {
             Err(AppResponse(format!(
-                "Port update request failed with status code: {}",
+                "Reannounce request failed with status code: {}",
                 status
             )))
         }
     }
 
-    fn interval(&self) -> Duration {
-        self.interval
+    fn pause(&self) -> Result<Vec<String>> {
+        let hashes: Vec<String> = self
+            .get_torrents(&ReannounceFilter::default())?
+            .iter()
+            .filter(|torrent| {
+                let state = torrent
+                    .get("state")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                !STOPPED_STATES.iter().any(|s| state.starts_with(s))
+            })
+            .filter_map(|torrent| torrent.get("hash").and_then(Value::as_str))
+            .map(str::to_string)
+            .collect();
+        if !hashes.is_empty() {
+            self.torrents_action(QB_STOP_ENDPOINT, QB_PAUSE_ENDPOINT, &hashes)?;
+        }
+        Ok(hashes)
+    }
+
+    fn resume(&self, torrents: &[String]) -> Result<()> {
+        self.torrents_action(QB_START_ENDPOINT, QB_RESUME_ENDPOINT, torrents)
     }
 
-    fn port_forward_path(&self) -> &Path {
-        self.port_forward_path.as_path()
+    fn port_mapping(&self) -> Result<Vec<(&'static str, bool)>> {
+        let preferences = self.get_preferences()?;
+        Ok(vec![(
+            UPNP,
+            preferences
+                .get(UPNP)
+                .and_then(Value::as_bool)
+                .unwrap_or_default(),
+        )])
+    }
+
+    fn bind_interface(&self, binding: &InterfaceBinding) -> Result<()> {
+        let mut settings = json!({NETWORK_INTERFACE: binding.interface});
+        if let Some(address) = &binding.address {
+            settings[INTERFACE_ADDRESS] = json!(address);
+        }
+        binding.enforce(
+            settings,
+            || self.get_preferences(),
+            |settings| self.set_preferences(settings.clone()),
+        )
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
     }
 }
 
@@ -119,25 +207,136 @@ impl Qbittorrent {
         )
     }
 
-    fn get_current_listen_port(&self) -> Result<u16> {
-        let client = &self.client;
-        let response = client.get(self.get_preference_endpoint()).send()?;
+    fn reannounce_endpoint(&self) -> String {
+        endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            QB_REANNOUNCE_ENDPOINT,
+        )
+    }
+
+    fn torrents_info_endpoint(&self, filter: &ReannounceFilter) -> Result<Url> {
+        let url = endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            QB_TORRENTS_INFO_ENDPOINT,
+        );
+        Url::parse_with_params(
+            url.as_str(),
+            filter
+                .category
+                .iter()
+                .map(|category| ("category", category.as_str())),
+        )
+        .map_err(|e| ParsingFailure(format!("{url} is not a valid URL -> {e}")))
+    }
+
+    fn torrents_trackers_endpoint(&self, hash: &str) -> Result<Url> {
+        let url = endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            QB_TORRENTS_TRACKERS_ENDPOINT,
+        );
+        Url::parse_with_params(url.as_str(), [("hash", hash)])
+            .map_err(|e| ParsingFailure(format!("{url} is not a valid URL -> {e}")))
+    }
+
+    /// Returns the hashes of the torrents in the category with any matching tracker
+    fn get_torrent_hashes(&self, filter: &ReannounceFilter) -> Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        for torrent in self.get_torrents(filter)? {
+            let Some(hash) = torrent.get("hash").and_then(Value::as_str) else {
+                continue;
+            };
+            // The tracker of the torrent info is only the current working tracker
+            if filter.tracker.is_none()
+                || self
+                    .get_trackers(hash)?
+                    .iter()
+                    .any(|tracker| filter.matches_tracker(tracker))
+            {
+                hashes.push(hash.to_string());
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Returns the torrents in the category of the filter
+    fn get_torrents(&self, filter: &ReannounceFilter) -> Result<Vec<Value>> {
+        self.get_array(self.torrents_info_endpoint(filter)?, "torrents info")
+    }
+
+    /// Returns the URLs of every tracker of the torrent
+    fn get_trackers(&self, hash: &str) -> Result<Vec<String>> {
+        Ok(self
+            .get_array(self.torrents_trackers_endpoint(hash)?, "torrent trackers")?
+            .iter()
+            .filter_map(|tracker| tracker.get("url").and_then(Value::as_str))
+            .map(str::to_string)
+            .collect())
+    }
 
+    fn get_array(&self, url: Url, name: &str) -> Result<Vec<Value>> {
+        let response = self.client.get(url).send()?;
         let status = response.status();
-        if status.is_success() {
+        if status == StatusCode::FORBIDDEN {
+            return Err(Session(format!("qBittorrent {name} status {status}")));
+        } else if !status.is_success() {
+            return Err(AppResponse(format!(
+                "{name} request failed with status code: {}",
+                status
+            )));
+        }
+        let json: Value = response.json()?;
+        trace!("{name} response json value: {}", json);
+        match json {
+            Value::Array(values) => Ok(values),
+            _ => Err(ParsingFailure(format!("{name} json value is not an array"))),
+        }
+    }
+
+    /// Sends the hashes to the endpoint, or the endpoint of older versions when it is not found
+    fn torrents_action(&self, path: &str, legacy_path: &str, hashes: &[String]) -> Result<()> {
+        let form = HashMap::from([("hashes", hashes.join(HASH_SEPARATOR))]);
+        let mut status = StatusCode::NOT_FOUND;
+        for path in [path, legacy_path] {
+            let url = endpoint(self.protocol, self.hostname.as_str(), self.port, path);
+            status = self.client.post(url).form(&form).send()?.status();
+            if status != StatusCode::NOT_FOUND {
+                break;
+            }
+        }
+        if status == StatusCode::FORBIDDEN {
+            Err(Session(format!("qBittorrent {path} status {status}")))
+        } else if status.is_success() {
+            Ok(())
+        } else {
+            Err(AppResponse(format!(
+                "{path} request failed with status code: {}",
+                status
+            )))
+        }
+    }
+
+    fn get_preferences(&self) -> Result<Value> {
+        let response = self.client.get(self.get_preference_endpoint()).send()?;
+        let status = response.status();
+        if status == StatusCode::FORBIDDEN {
+            Err(Session(format!(
+                "qBittorrent get preference status {status}"
+            )))
+        } else if status.is_success() {
             let json: Value = response.json()?;
             trace!("get preference response json value: {}", json);
-            Ok(json
-                .as_object()
-                .ok_or_else(|| ParsingFailure("unable to parse preferences json object".into()))?
-                .get("listen_port")
-                .unwrap_or_default()
-                .as_number()
-                .ok_or_else(|| {
-                    ParsingFailure("current listen port json value is not a number".into())
-                })?
-                .as_u64()
-                .unwrap_or_default() as u16)
+            match json.is_object() {
+                true => Ok(json),
+                false => Err(ParsingFailure(
+                    "unable to parse preferences json object".into(),
+                )),
+            }
         } else {
             Err(AppResponse(format!(
                 "get preference request failed with status code: {}",
@@ -145,13 +344,44 @@ impl Qbittorrent {
             )))
         }
     }
+
+    fn set_preferences(&self, preferences: Value) -> Result<()> {
+        let form = HashMap::from([("json", preferences.to_string())]);
+        let response = self
+            .client
+            .post(self.set_preference_endpoint())
+            .form(&form)
+            .send()?;
+        let status = response.status();
+        if status == StatusCode::FORBIDDEN {
+            Err(Session(format!(
+                "qBittorrent set preference status {status}"
+            )))
+        } else if status.is_success() {
+            Ok(())
+        } else {
+            Err(AppResponse(format!(
+                "set preference request failed with status code: {}",
+                status
+            )))
+        }
+    }
+
+    fn get_current_listen_port(&self) -> Result<u16> {
+        self.get_preferences()?
+            .get(LISTEN_PORT)
+            .and_then(Value::as_u64)
+            .and_then(|port| u16::try_from(port).ok())
+            .ok_or_else(|| ParsingFailure("current listen port json value is not a number".into()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::Error::Binding;
     use httpmock::MockServer;
-    use httpmock::prelude::POST;
+    use httpmock::prelude::{GET, POST};
 
     #[test]
     fn login() {
@@ -174,7 +404,7 @@ mod tests {
             port: server.port(),
             username: USER.to_string(),
             password: PASSWORD.to_string(),
-            port_forward_path: Default::default(),
+            disable_port_mapping: false,
             interval: Default::default(),
         };
         let app_fail = Qbittorrent {
@@ -184,7 +414,7 @@ mod tests {
             port: server.port(),
             username: Default::default(),
             password: PASSWORD.to_string(),
-            port_forward_path: Default::default(),
+            disable_port_mapping: false,
             interval: Default::default(),
         };
         let result_success = app_success.login();
@@ -194,4 +424,195 @@ mod tests {
         let result_fail = app_fail.login();
         assert!(result_fail.is_err());
     }
+
+    #[test]
+    fn expired_session() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path(QB_GET_PREFERENCES_ENDPOINT);
+            then.status(403).body("Forbidden");
+        });
+        let app = Qbittorrent {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            username: Default::default(),
+            password: Default::default(),
+            disable_port_mapping: false,
+            interval: Default::default(),
+        };
+        let result = app.current_port(51413);
+        assert!(matches!(result, Err(Session(_))));
+        assert!(result.unwrap_err().is_authorization());
+    }
+
+    #[test]
+    fn reannounce_filtered() {
+        let server = MockServer::start();
+        let info = server.mock(|when, then| {
+            when.method(GET)
+                .path(QB_TORRENTS_INFO_ENDPOINT)
+                .query_param("category", "tv shows");
+            then.status(200).json_body(serde_json::json!([
+                {"hash": "aaa", "tracker": "https://tracker.example.com/announce"},
+                {"hash": "bbb", "tracker": "https://other.example.org/announce"},
+                {"hash": "ccc", "tracker": ""}
+            ]));
+        });
+        let trackers = |hash: &str, urls: &[&str]| {
+            let urls: Vec<_> = urls.iter().map(|url| json!({"url": url})).collect();
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path(QB_TORRENTS_TRACKERS_ENDPOINT)
+                    .query_param("hash", hash);
+                then.status(200).json_body(json!(urls));
+            });
+        };
+        trackers("aaa", &["https://tracker.example.com/announce"]);
+        trackers(
+            "bbb",
+            &["** [DHT] **", "https://other.example.org/announce"],
+        );
+        // Not working so it is not the tracker of the torrent info
+        trackers("ccc", &["udp://tracker.example.com:1337"]);
+        let reannounce = server.mock(|when, then| {
+            when.method(POST)
+                .path(QB_REANNOUNCE_ENDPOINT)
+                .form_urlencoded_tuple("hashes", "aaa|ccc");
+            then.status(200);
+        });
+        let app = Qbittorrent {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            username: Default::default(),
+            password: Default::default(),
+            disable_port_mapping: false,
+            interval: Default::default(),
+        };
+        let filter = ReannounceFilter {
+            category: Some("tv shows".into()),
+            tracker: Some("tracker.example.com".into()),
+        };
+
+        assert!(app.reannounce(&filter).is_ok());
+        info.assert();
+        reannounce.assert();
+    }
+
+    #[test]
+    fn bind_interface() {
+        let server = MockServer::start();
+        let mut preferences = server.mock(|when, then| {
+            when.method(GET).path(QB_GET_PREFERENCES_ENDPOINT);
+            then.status(200)
+                .json_body(json!({NETWORK_INTERFACE: "", INTERFACE_ADDRESS: ""}));
+        });
+        let set = server.mock(|when, then| {
+            when.method(POST)
+                .path(QB_SET_PREFERENCES_ENDPOINT)
+                .form_urlencoded_tuple("json", r#"{"current_network_interface":"tun0"}"#);
+            then.status(200);
+        });
+        let app = Qbittorrent {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            username: Default::default(),
+            password: Default::default(),
+            disable_port_mapping: false,
+            interval: Default::default(),
+        };
+        let mut binding = InterfaceBinding {
+            interface: "tun0".into(),
+            address: None,
+            check_only: true,
+        };
+        assert!(matches!(app.bind_interface(&binding), Err(Binding(_))));
+        set.assert_calls(0);
+
+        // Set but the preference did not change
+        binding.check_only = false;
+        assert!(matches!(app.bind_interface(&binding), Err(Binding(_))));
+        set.assert_calls(1);
+
+        preferences.delete();
+        server.mock(|when, then| {
+            when.method(GET).path(QB_GET_PREFERENCES_ENDPOINT);
+            then.status(200)
+                .json_body(json!({NETWORK_INTERFACE: "tun0", INTERFACE_ADDRESS: ""}));
+        });
+        assert!(app.bind_interface(&binding).is_ok());
+        set.assert_calls(1);
+    }
+
+    #[test]
+    fn disable_upnp() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path(QB_GET_PREFERENCES_ENDPOINT);
+            then.status(200)
+                .json_body(json!({LISTEN_PORT: 51413, UPNP: true}));
+        });
+        let set = server.mock(|when, then| {
+            when.method(POST)
+                .path(QB_SET_PREFERENCES_ENDPOINT)
+                .form_urlencoded_tuple("json", r#"{"listen_port":51413,"upnp":false}"#);
+            then.status(200);
+        });
+        let app = Qbittorrent {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            username: Default::default(),
+            password: Default::default(),
+            disable_port_mapping: true,
+            interval: Default::default(),
+        };
+
+        assert_eq!(app.port_mapping().unwrap(), vec![(UPNP, true)]);
+        assert!(app.set_port(51413).is_ok());
+        set.assert();
+    }
+
+    #[test]
+    fn pause_active_torrents() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path(QB_TORRENTS_INFO_ENDPOINT);
+            then.status(200).json_body(json!([
+                {"hash": "aaa", "state": "uploading"},
+                {"hash": "bbb", "state": "pausedUP"},
+                {"hash": "ccc", "state": "stalledDL"}
+            ]));
+        });
+        let stop = server.mock(|when, then| {
+            when.method(POST).path(QB_STOP_ENDPOINT);
+            then.status(404);
+        });
+        let pause = server.mock(|when, then| {
+            when.method(POST)
+                .path(QB_PAUSE_ENDPOINT)
+                .form_urlencoded_tuple("hashes", "aaa|ccc");
+            then.status(200);
+        });
+        let app = Qbittorrent {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            username: Default::default(),
+            password: Default::default(),
+            disable_port_mapping: false,
+            interval: Default::default(),
+        };
+
+        assert_eq!(app.pause().unwrap(), vec!["aaa", "ccc"]);
+        stop.assert();
+        pause.assert();
+    }
 }
@@ -0,0 +1,197 @@
+use crate::apps::{App, Connection, Protocol, endpoint, single_port};
+use crate::error::Error::{AppResponse, Authorization, ParsingFailure, PortUpdate};
+use crate::error::Result;
+use crate::xmlrpc::{XmlRpcRequest, XmlRpcResponse, XmlRpcValue};
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use std::time::Duration;
+use tracing::{debug, info, trace};
+
+const RTORRENT_ENDPOINT: &str = "/RPC2";
+const CLIENT_VERSION_METHOD: &str = "system.client_version";
+const PORT_RANGE_METHOD: &str = "network.port_range";
+const SET_PORT_RANGE_METHOD: &str = "network.port_range.set";
+const SET_PORT_OPEN_METHOD: &str = "network.port_open.set";
+const XML_CONTENT_TYPE: &str = "text/xml";
+
+pub struct RTorrent {
+    pub connection: Connection,
+    pub protocol: Protocol,
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub interval: Duration,
+}
+
+impl App for RTorrent {
+    fn login(&self) -> Result<()> {
+        let version = self.call(CLIENT_VERSION_METHOD, vec![])?;
+        debug!(
+            "rTorrent login successful with client version {}",
+            version.as_str().unwrap_or_default()
+        );
+        Ok(())
+    }
+
+    fn set_port(&self, port: u16) -> Result<()> {
+        self.call(
+            SET_PORT_RANGE_METHOD,
+            vec!["".into(), format!("{port}-{port}").into()],
+        )?;
+        self.call(SET_PORT_OPEN_METHOD, vec!["".into(), XmlRpcValue::Int(1)])?;
+        let actual_port = self.get_current_port_range()?;
+        debug!("actual_port: {:?}", actual_port);
+        if (port, port) == actual_port {
+            info!("Port updated to {}", port);
+            Ok(())
+        } else {
+            Err(PortUpdate(format!(
+                "Actual port range {}-{} does not match expected port number {}",
+                actual_port.0, actual_port.1, port
+            )))
+        }
+    }
+
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        Ok(Some(single_port(self.get_current_port_range()?)))
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl RTorrent {
+    fn endpoint(&self) -> String {
+        endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            RTORRENT_ENDPOINT,
+        )
+    }
+
+    fn get_current_port_range(&self) -> Result<(u16, u16)> {
+        let value = self.call(PORT_RANGE_METHOD, vec!["".into()])?;
+        parse_port_range(
+            value.as_str().ok_or_else(|| {
+                ParsingFailure(format!("{PORT_RANGE_METHOD} value is not a string"))
+            })?,
+        )
+    }
+
+    fn call(&self, method: &str, params: Vec<XmlRpcValue>) -> Result<XmlRpcValue> {
+        let request = XmlRpcRequest::new(method, params).to_xml();
+        trace!("{}", request);
+        let body = match &self.connection {
+            Connection::Http(client) => self.send_http(client, method, request)?,
+            Connection::Scgi(address) => String::from_utf8_lossy(
+                address
+                    .send(RTORRENT_ENDPOINT, request.as_bytes())?
+                    .as_slice(),
+            )
+            .into_owned(),
+        };
+        trace!("rTorrent {method} response: {}", body);
+        match XmlRpcResponse::from_xml(body.as_str())? {
+            XmlRpcResponse::Success(value) => Ok(value),
+            XmlRpcResponse::Fault { code, message } => Err(AppResponse(format!(
+                "rTorrent {method} fault {code} with message -> {message}"
+            ))),
+        }
+    }
+
+    fn send_http(&self, client: &Client, method: &str, request: String) -> Result<String> {
+        let mut builder = client
+            .post(self.endpoint())
+            .header(CONTENT_TYPE, XML_CONTENT_TYPE)
+            .body(request);
+        if !self.password.is_empty() {
+            builder = builder.basic_auth(&self.username, Some(&self.password));
+        }
+        let response = builder.send()?;
+
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            debug!("rTorrent request failed with status code: {}", status);
+            Err(Authorization)
+        } else if status.is_success() {
+            Ok(response.text()?)
+        } else {
+            Err(AppResponse(format!(
+                "rTorrent {method} request failed with status code: {}",
+                status
+            )))
+        }
+    }
+}
+
+/// Parses rTorrent's `first-last` port range format
+fn parse_port_range(value: &str) -> Result<(u16, u16)> {
+    let (first, last) = value
+        .trim()
+        .split_once('-')
+        .ok_or_else(|| ParsingFailure(format!("port range {value} is not valid")))?;
+    Ok((first.parse()?, last.parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::POST;
+
+    #[test]
+    fn set_port() {
+        const PORT: u16 = 51413;
+        let server = MockServer::start();
+        let set_range = server.mock(|when, then| {
+            when.method(POST)
+                .path(RTORRENT_ENDPOINT)
+                .body_includes(format!("<methodName>{SET_PORT_RANGE_METHOD}</methodName>"))
+                .body_includes("<string>51413-51413</string>");
+            then.status(200).body(
+                "<methodResponse><params><param><value><i4>0</i4></value></param></params></methodResponse>",
+            );
+        });
+        let set_open = server.mock(|when, then| {
+            when.method(POST)
+                .path(RTORRENT_ENDPOINT)
+                .body_includes(format!("<methodName>{SET_PORT_OPEN_METHOD}</methodName>"));
+            then.status(200).body(
+                "<methodResponse><params><param><value><i4>0</i4></value></param></params></methodResponse>",
+            );
+        });
+        let get_range = server.mock(|when, then| {
+            when.method(POST)
+                .path(RTORRENT_ENDPOINT)
+                .body_includes(format!("<methodName>{PORT_RANGE_METHOD}</methodName>"));
+            then.status(200).body(
+                "<methodResponse><params><param><value><string>51413-51413</string></value></param></params></methodResponse>",
+            );
+        });
+
+        let app = RTorrent {
+            connection: Connection::Http(Default::default()),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            username: Default::default(),
+            password: Default::default(),
+            interval: Default::default(),
+        };
+        let result = app.set_port(PORT);
+        set_range.assert();
+        set_open.assert();
+        get_range.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn port_range() {
+        assert_eq!(parse_port_range("6881-6999").unwrap(), (6881, 6999));
+        assert!(parse_port_range("6881").is_err());
+    }
+}
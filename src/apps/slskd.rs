@@ -0,0 +1,242 @@
+use crate::apps::{App, Protocol, check_status, endpoint};
+use crate::error::Error::{ParsingFailure, PortUpdate};
+use crate::error::Result;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::cell::RefCell;
+use std::time::Duration;
+use tracing::{debug, info, trace};
+
+const SLSKD_SESSION_ENDPOINT: &str = "/api/v0/session";
+const SLSKD_OPTIONS_ENDPOINT: &str = "/api/v0/options";
+const SLSKD_OPTIONS_YAML_ENDPOINT: &str = "/api/v0/options/yaml";
+const API_KEY_HEADER: &str = "X-API-Key";
+const SOULSEEK_SECTION: &str = "soulseek:";
+const LISTEN_PORT_KEY: &str = "listen_port:";
+const YAML_INDENT: &str = "  ";
+
+#[derive(Debug, Deserialize)]
+struct SessionResponse {
+    token: String,
+}
+
+/// Authenticates with the API key when one is set, otherwise with a JWT from the username and password
+pub struct Slskd {
+    pub client: Client,
+    pub protocol: Protocol,
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub api_key: String,
+    pub token: RefCell<Option<String>>,
+    pub interval: Duration,
+}
+
+impl App for Slskd {
+    fn login(&self) -> Result<()> {
+        if self.api_key.is_empty() {
+            let response = check_status(
+                "slskd",
+                self.client
+                    .post(self.endpoint(SLSKD_SESSION_ENDPOINT))
+                    .json(&json!({"username": self.username, "password": self.password}))
+                    .send()?,
+            )?;
+            let session: SessionResponse = response.json()?;
+            self.token.replace(Some(session.token));
+        } else {
+            self.get_current_listen_port()?;
+        }
+        debug!("slskd login successful");
+        Ok(())
+    }
+
+    fn set_port(&self, port: u16) -> Result<()> {
+        let response = check_status(
+            "slskd",
+            self.request(self.client.get(self.endpoint(SLSKD_OPTIONS_YAML_ENDPOINT)))
+                .send()?,
+        )?;
+        let yaml: String = response.json()?;
+        trace!("slskd options yaml: {}", yaml);
+
+        check_status(
+            "slskd",
+            self.request(self.client.post(self.endpoint(SLSKD_OPTIONS_YAML_ENDPOINT)))
+                .json(&set_listen_port(yaml.as_str(), port))
+                .send()?,
+        )?;
+
+        let actual_port = self.get_current_listen_port()?;
+        debug!("actual_port: {:?}", actual_port);
+        if port == actual_port {
+            info!("Port updated to {}", port);
+            Ok(())
+        } else {
+            Err(PortUpdate(format!(
+                "Actual port {} does not match expected port number {}",
+                actual_port, port
+            )))
+        }
+    }
+
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        Ok(Some(self.get_current_listen_port()?))
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Slskd {
+    fn endpoint(&self, path: &str) -> String {
+        endpoint(self.protocol, self.hostname.as_str(), self.port, path)
+    }
+
+    fn request(&self, builder: RequestBuilder) -> RequestBuilder {
+        if !self.api_key.is_empty() {
+            return builder.header(API_KEY_HEADER, self.api_key.as_str());
+        }
+        match self.token.borrow().as_ref() {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+
+    fn get_current_listen_port(&self) -> Result<u16> {
+        let response = check_status(
+            "slskd",
+            self.request(self.client.get(self.endpoint(SLSKD_OPTIONS_ENDPOINT)))
+                .send()?,
+        )?;
+        let json: Value = response.json()?;
+        trace!("get options response json value: {}", json);
+        json.pointer("/soulseek/listenPort")
+            .and_then(|v| v.as_u64())
+            .and_then(|v| u16::try_from(v).ok())
+            .ok_or_else(|| ParsingFailure("soulseek listenPort json value is not a port".into()))
+    }
+}
+
+/// Sets `soulseek.listen_port` in the YAML while keeping the rest of the document and its comments
+fn set_listen_port(yaml: &str, port: u16) -> String {
+    let mut lines: Vec<String> = yaml.lines().map(|line| line.to_string()).collect();
+    let section = lines
+        .iter()
+        .position(|line| line.split('#').next().unwrap_or_default().trim_end() == SOULSEEK_SECTION);
+
+    match section {
+        Some(section) => {
+            let children = lines[section + 1..]
+                .iter()
+                .take_while(|line| line.trim().is_empty() || line.starts_with([' ', '\t', '#']))
+                .count();
+            let indent = lines[section + 1..section + 1 + children]
+                .iter()
+                .find(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+                .map(|line| line[..line.len() - line.trim_start().len()].to_string())
+                .unwrap_or(YAML_INDENT.to_string());
+            // Only a direct child of the section, a nested mapping can have its own listen_port
+            let key = format!("{indent}{LISTEN_PORT_KEY}");
+            let child = lines[section + 1..section + 1 + children]
+                .iter()
+                .position(|line| line.starts_with(key.as_str()));
+            let line = format!("{key} {port}");
+            match child {
+                Some(child) => lines[section + 1 + child] = line,
+                None => lines.insert(section + 1, line),
+            }
+        }
+        None => {
+            lines.push(SOULSEEK_SECTION.to_string());
+            lines.push(format!("{YAML_INDENT}{LISTEN_PORT_KEY} {port}"));
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::{GET, POST};
+
+    #[test]
+    fn listen_port_yaml() {
+        const YAML: &str = "web:\n  port: 5030\nsoulseek:\n  # listen_port: 50300\n  description: slskd\n    user\n  listen_port: 50300 # forwarded\nshares:\n  directories: []\n";
+        assert_eq!(
+            set_listen_port(YAML, 51413),
+            "web:\n  port: 5030\nsoulseek:\n  # listen_port: 50300\n  description: slskd\n    user\n  listen_port: 51413\nshares:\n  directories: []\n"
+        );
+        assert_eq!(
+            set_listen_port("soulseek:\n    username: someuser\n", 51413),
+            "soulseek:\n    listen_port: 51413\n    username: someuser\n"
+        );
+        assert_eq!(
+            set_listen_port(
+                "soulseek:\n  distributed_network:\n    listen_port: 50301\n",
+                51413
+            ),
+            "soulseek:\n  listen_port: 51413\n  distributed_network:\n    listen_port: 50301\n"
+        );
+        assert_eq!(
+            set_listen_port("web:\n  port: 5030", 51413),
+            "web:\n  port: 5030\nsoulseek:\n  listen_port: 51413\n"
+        );
+    }
+
+    #[test]
+    fn set_port_with_jwt() {
+        const TOKEN: &str = "sometoken";
+        let server = MockServer::start();
+        let session = server.mock(|when, then| {
+            when.method(POST)
+                .path(SLSKD_SESSION_ENDPOINT)
+                .json_body(json!({"username": "slskd", "password": "slskd"}));
+            then.status(200).json_body(json!({"token": TOKEN}));
+        });
+        let get_yaml = server.mock(|when, then| {
+            when.method(GET)
+                .path(SLSKD_OPTIONS_YAML_ENDPOINT)
+                .header("authorization", format!("Bearer {TOKEN}"));
+            then.status(200)
+                .json_body(json!("soulseek:\n  listen_port: 50300\n"));
+        });
+        let post_yaml = server.mock(|when, then| {
+            when.method(POST)
+                .path(SLSKD_OPTIONS_YAML_ENDPOINT)
+                .header("authorization", format!("Bearer {TOKEN}"))
+                .json_body(json!("soulseek:\n  listen_port: 51413\n"));
+            then.status(200);
+        });
+        let get_options = server.mock(|when, then| {
+            when.method(GET)
+                .path(SLSKD_OPTIONS_ENDPOINT)
+                .header("authorization", format!("Bearer {TOKEN}"));
+            then.status(200)
+                .json_body(json!({"soulseek": {"listenPort": 51413}}));
+        });
+
+        let app = Slskd {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            username: "slskd".to_string(),
+            password: "slskd".to_string(),
+            api_key: Default::default(),
+            token: Default::default(),
+            interval: Default::default(),
+        };
+        assert!(app.login().is_ok());
+        let result = app.set_port(51413);
+        session.assert();
+        get_yaml.assert();
+        post_yaml.assert();
+        get_options.assert();
+        assert!(result.is_ok());
+    }
+}
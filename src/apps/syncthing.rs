@@ -0,0 +1,264 @@
+use crate::apps::{App, Protocol, check_status, endpoint};
+use crate::error::Error::{ParsingFailure, PortUpdate};
+use crate::error::Result;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tracing::{debug, info, trace};
+
+const SYNCTHING_PING_ENDPOINT: &str = "/rest/system/ping";
+const SYNCTHING_OPTIONS_ENDPOINT: &str = "/rest/config/options";
+const API_KEY_HEADER: &str = "X-API-Key";
+const LISTEN_ADDRESSES: &str = "listenAddresses";
+const TCP_SCHEME: &str = "tcp";
+const QUIC_SCHEME: &str = "quic";
+/// Expands to TCP and QUIC on port 22000 and the relays, it is replaced by addresses with the forwarded port
+const DEFAULT_ADDRESS: &str = "default";
+/// Relay pool endpoint included in `default`
+const RELAY_ADDRESS: &str = "dynamic+https://relays.syncthing.net/endpoint";
+
+pub struct Syncthing {
+    pub client: Client,
+    pub protocol: Protocol,
+    pub hostname: String,
+    pub port: u16,
+    pub api_key: String,
+    pub interval: Duration,
+}
+
+impl App for Syncthing {
+    fn login(&self) -> Result<()> {
+        check_status(
+            "Syncthing",
+            self.request(self.client.get(self.ping_endpoint())).send()?,
+        )?;
+        debug!("Syncthing login successful");
+        Ok(())
+    }
+
+    fn set_port(&self, port: u16) -> Result<()> {
+        let listen_addresses = replace_listen_addresses(self.get_listen_addresses()?, port);
+        debug!("{LISTEN_ADDRESSES}: {:?}", listen_addresses);
+        check_status(
+            "Syncthing",
+            self.request(self.client.patch(self.options_endpoint()))
+                .json(&json!({LISTEN_ADDRESSES: listen_addresses}))
+                .send()?,
+        )?;
+
+        let actual_addresses = self.get_listen_addresses()?;
+        debug!("actual {LISTEN_ADDRESSES}: {:?}", actual_addresses);
+        if actual_addresses == listen_addresses {
+            info!("Port updated to {}", port);
+            Ok(())
+        } else {
+            Err(PortUpdate(format!(
+                "Actual listen addresses {:?} do not match expected port number {}",
+                actual_addresses, port
+            )))
+        }
+    }
+
+    fn current_port(&self, port: u16) -> Result<Option<u16>> {
+        let addresses = self.get_listen_addresses()?;
+        Ok(Some(
+            match replace_listen_addresses(addresses.clone(), port) == addresses {
+                true => port,
+                false => listen_port(addresses.as_slice()),
+            },
+        ))
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Syncthing {
+    fn ping_endpoint(&self) -> String {
+        endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            SYNCTHING_PING_ENDPOINT,
+        )
+    }
+
+    fn options_endpoint(&self) -> String {
+        endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            SYNCTHING_OPTIONS_ENDPOINT,
+        )
+    }
+
+    fn request(&self, builder: RequestBuilder) -> RequestBuilder {
+        builder.header(API_KEY_HEADER, self.api_key.as_str())
+    }
+
+    fn get_listen_addresses(&self) -> Result<Vec<String>> {
+        let response = check_status(
+            "Syncthing",
+            self.request(self.client.get(self.options_endpoint()))
+                .send()?,
+        )?;
+        let json: Value = response.json()?;
+        trace!("get options response json value: {}", json);
+        json.get(LISTEN_ADDRESSES)
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| {
+                ParsingFailure(format!("{LISTEN_ADDRESSES} json value is not an array"))
+            })?
+            .iter()
+            .map(|v| {
+                v.as_str().map(|v| v.to_string()).ok_or_else(|| {
+                    ParsingFailure(format!("{LISTEN_ADDRESSES} json value is not a string"))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns the scheme without the IPv4 or IPv6 suffix, ie. `tcp4://` is `tcp`
+fn scheme(address: &str) -> Option<&str> {
+    address
+        .split_once("://")
+        .map(|(scheme, _)| scheme.trim_end_matches(['4', '6']))
+}
+
+/// Returns the port of the first TCP address, or 0 when there is none, ie. only `default`
+fn listen_port(addresses: &[String]) -> u16 {
+    addresses
+        .iter()
+        .filter(|address| scheme(address.as_str()) == Some(TCP_SCHEME))
+        .find_map(|address| address.rsplit_once(':')?.1.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Replaces the port of the address and keeps the scheme and host, ie. `tcp6://[::]:22000`
+fn with_port(address: &str, port: u16) -> String {
+    let (scheme, rest) = address.split_once("://").unwrap_or_default();
+    let host = match rest.starts_with('[') {
+        true => rest.find(']').map_or(rest, |end| &rest[..=end]),
+        false => rest.rsplit_once(':').map_or(rest, |(host, _)| host),
+    };
+    format!("{scheme}://{host}:{port}")
+}
+
+/// Replaces the port of the TCP and QUIC addresses, keeps every other address in place and replaces `default` with
+/// the addresses it expands to
+fn replace_listen_addresses(addresses: Vec<String>, port: u16) -> Vec<String> {
+    let mut replaced = Vec::new();
+    let mut relays = false;
+    for address in addresses {
+        let address = match scheme(address.as_str()) {
+            Some(TCP_SCHEME | QUIC_SCHEME) => with_port(address.as_str(), port),
+            _ if address == DEFAULT_ADDRESS => {
+                relays = true;
+                continue;
+            }
+            _ => address,
+        };
+        if !replaced.contains(&address) {
+            replaced.push(address);
+        }
+    }
+    for scheme_name in [TCP_SCHEME, QUIC_SCHEME] {
+        if !replaced
+            .iter()
+            .any(|address| scheme(address.as_str()) == Some(scheme_name))
+        {
+            replaced.push(format!("{scheme_name}://0.0.0.0:{port}"));
+        }
+    }
+    if relays && !replaced.iter().any(|address| address == RELAY_ADDRESS) {
+        replaced.push(RELAY_ADDRESS.to_string());
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::{GET, PATCH};
+
+    #[test]
+    fn listen_addresses() {
+        let addresses = vec![
+            "tcp4://0.0.0.0:22000".to_string(),
+            "dynamic+https://relays.syncthing.net/endpoint".to_string(),
+            "tcp6://[::]:22000".to_string(),
+            "quic://0.0.0.0:22000".to_string(),
+        ];
+        assert_eq!(
+            replace_listen_addresses(addresses, 51413),
+            vec![
+                "tcp4://0.0.0.0:51413",
+                "dynamic+https://relays.syncthing.net/endpoint",
+                "tcp6://[::]:51413",
+                "quic://0.0.0.0:51413",
+            ]
+        );
+        assert_eq!(
+            replace_listen_addresses(vec!["default".to_string()], 51413),
+            vec!["tcp://0.0.0.0:51413", "quic://0.0.0.0:51413", RELAY_ADDRESS]
+        );
+        assert_eq!(
+            replace_listen_addresses(
+                vec!["default".to_string(), RELAY_ADDRESS.to_string()],
+                51413
+            ),
+            vec![RELAY_ADDRESS, "tcp://0.0.0.0:51413", "quic://0.0.0.0:51413"]
+        );
+        assert_eq!(
+            replace_listen_addresses(vec!["quic6://[::1]".to_string()], 51413),
+            vec!["quic6://[::1]:51413", "tcp://0.0.0.0:51413"]
+        );
+    }
+
+    #[test]
+    fn set_port() {
+        const API_KEY: &str = "someapikey";
+        let server = MockServer::start();
+        let mut get = server.mock(|when, then| {
+            when.method(GET)
+                .path(SYNCTHING_OPTIONS_ENDPOINT)
+                .header(API_KEY_HEADER, API_KEY);
+            then.status(200).json_body(
+                json!({LISTEN_ADDRESSES: ["tcp://0.0.0.0:22000", "quic://0.0.0.0:22000"]}),
+            );
+        });
+        let patch = server.mock(|when, then| {
+            when.method(PATCH)
+                .path(SYNCTHING_OPTIONS_ENDPOINT)
+                .header(API_KEY_HEADER, API_KEY)
+                .json_body(
+                    json!({LISTEN_ADDRESSES: ["tcp://0.0.0.0:51413", "quic://0.0.0.0:51413"]}),
+                );
+            then.status(200);
+        });
+
+        let app = Syncthing {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            api_key: API_KEY.to_string(),
+            interval: Default::default(),
+        };
+        // The read back still returns the old addresses
+        assert!(matches!(app.set_port(51413), Err(PortUpdate(_))));
+        patch.assert();
+
+        get.delete();
+        server.mock(|when, then| {
+            when.method(GET).path(SYNCTHING_OPTIONS_ENDPOINT);
+            then.status(200).json_body(
+                json!({LISTEN_ADDRESSES: ["tcp://0.0.0.0:51413", "quic://0.0.0.0:51413"]}),
+            );
+        });
+        assert!(app.set_port(51413).is_ok());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::apps::{App, Protocol, endpoint};
+use crate::error::Error::{AppResponse, Authorization, ParsingFailure, PortUpdate};
+use crate::error::Result;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::cell::RefCell;
+use std::time::Duration;
+use tracing::{debug, info, trace};
+
+const TRANSMISSION_ENDPOINT: &str = "/transmission/rpc";
+const SESSION_ID_HEADER: &str = "X-Transmission-Session-Id";
+const SESSION_GET_METHOD: &str = "session-get";
+const SESSION_SET_METHOD: &str = "session-set";
+const PEER_PORT_ARGUMENT: &str = "peer-port";
+const RESULT_SUCCESS: &str = "success";
+
+#[derive(Debug, Clone, Serialize)]
+struct TransmissionRequest {
+    method: String,
+    arguments: Value,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TransmissionResponse {
+    result: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+pub struct Transmission {
+    pub client: Client,
+    pub protocol: Protocol,
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub interval: Duration,
+    pub session_id: RefCell<Option<String>>,
+}
+
+impl App for Transmission {
+    fn login(&self) -> Result<()> {
+        self.get_current_peer_port()?;
+        debug!("Transmission login successful");
+        Ok(())
+    }
+
+    fn set_port(&self, port: u16) -> Result<()> {
+        self.send_rpc_request(SESSION_SET_METHOD, json!({PEER_PORT_ARGUMENT: port}))?;
+        let actual_port = self.get_current_peer_port()?;
+        debug!("actual_port: {:?}", actual_port);
+        if port == actual_port {
+            info!("Port updated to {}", port);
+            Ok(())
+        } else {
+            Err(PortUpdate(format!(
+                "Actual port {} does not match expected port number {}",
+                actual_port, port
+            )))
+        }
+    }
+
+    fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+        Ok(Some(self.get_current_peer_port()?))
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Transmission {
+    fn endpoint(&self) -> String {
+        endpoint(
+            self.protocol,
+            self.hostname.as_str(),
+            self.port,
+            TRANSMISSION_ENDPOINT,
+        )
+    }
+
+    fn get_current_peer_port(&self) -> Result<u16> {
+        let response =
+            self.send_rpc_request(SESSION_GET_METHOD, json!({"fields": [PEER_PORT_ARGUMENT]}))?;
+        let port = response
+            .arguments
+            .get(PEER_PORT_ARGUMENT)
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| {
+                ParsingFailure(format!("{PEER_PORT_ARGUMENT} json value is not a number"))
+            })?;
+        u16::try_from(port).map_err(|_| {
+            ParsingFailure(format!("{PEER_PORT_ARGUMENT} value {port} is out of range"))
+        })
+    }
+
+    /// Sends the request and repeats it once with a new session ID if the current one was rejected
+    fn send_rpc_request(&self, method: &str, arguments: Value) -> Result<TransmissionResponse> {
+        let request = TransmissionRequest {
+            method: method.into(),
+            arguments,
+        };
+        let mut response = self.post(&request)?;
+        if response.status() == StatusCode::CONFLICT {
+            let session_id = response
+                .headers()
+                .get(SESSION_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| {
+                    AppResponse(format!("Transmission did not return {SESSION_ID_HEADER}"))
+                })?
+                .to_string();
+            debug!("Transmission session ID updated");
+            self.session_id.replace(Some(session_id));
+            response = self.post(&request)?;
+        }
+
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            debug!("Transmission request failed with status code: {}", status);
+            Err(Authorization)
+        } else if status.is_success() {
+            let response: TransmissionResponse = response.json().map_err(|e| {
+                ParsingFailure(format!(
+                    "Could not parse Transmission response from json -> {e}"
+                ))
+            })?;
+            trace!("Transmission {method} response: {:?}", response);
+            if response.result == RESULT_SUCCESS {
+                Ok(response)
+            } else {
+                Err(AppResponse(format!(
+                    "Transmission {method} with message -> {}",
+                    response.result
+                )))
+            }
+        } else {
+            Err(AppResponse(format!(
+                "Transmission {method} request failed with status code: {}",
+                status
+            )))
+        }
+    }
+
+    fn post(&self, request: &TransmissionRequest) -> Result<Response> {
+        let mut builder = self.client.post(self.endpoint()).json(request);
+        if let Some(session_id) = self.session_id.borrow().as_ref() {
+            builder = builder.header(SESSION_ID_HEADER, session_id);
+        }
+        if !self.password.is_empty() {
+            builder = builder.basic_auth(&self.username, Some(&self.password));
+        }
+        Ok(builder.send()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::POST;
+
+    const SESSION_ID: &str = "transmission-session-id";
+
+    fn transmission(server: &MockServer) -> Transmission {
+        Transmission {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            username: Default::default(),
+            password: Default::default(),
+            interval: Default::default(),
+            session_id: Default::default(),
+        }
+    }
+
+    #[test]
+    fn set_port_with_session_handshake() {
+        const PORT: u16 = 51413;
+        let server = MockServer::start();
+        let conflict = server.mock(|when, then| {
+            when.method(POST)
+                .path(TRANSMISSION_ENDPOINT)
+                .header_missing(SESSION_ID_HEADER);
+            then.status(409).header(SESSION_ID_HEADER, SESSION_ID);
+        });
+        let session_set = server.mock(|when, then| {
+            when.method(POST)
+                .path(TRANSMISSION_ENDPOINT)
+                .header(SESSION_ID_HEADER, SESSION_ID)
+                .json_body_includes(r#"{"method":"session-set","arguments":{"peer-port":51413}}"#);
+            then.status(200)
+                .json_body(json!({"result": "success", "arguments": {}}));
+        });
+        let session_get = server.mock(|when, then| {
+            when.method(POST)
+                .path(TRANSMISSION_ENDPOINT)
+                .header(SESSION_ID_HEADER, SESSION_ID)
+                .json_body_includes(r#"{"method":"session-get"}"#);
+            then.status(200)
+                .json_body(json!({"result": "success", "arguments": {"peer-port": PORT}}));
+        });
+
+        let app = transmission(&server);
+        let result = app.set_port(PORT);
+        conflict.assert();
+        session_set.assert();
+        session_get.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn login_unauthorized() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path(TRANSMISSION_ENDPOINT);
+            then.status(401);
+        });
+
+        let app = transmission(&server);
+        assert!(matches!(app.login(), Err(Authorization)));
+    }
+}
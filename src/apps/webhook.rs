@@ -0,0 +1,206 @@
+use crate::apps::{App, Protocol, check_status, endpoint, substitute_port};
+use crate::error::Error::{ParsingFailure, PortUpdate};
+use crate::error::Result;
+use reqwest::Method;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{debug, info, trace};
+
+const HEADER_SEPARATOR: char = ':';
+
+/// Request used to read the current port back after the update
+pub struct WebhookVerify {
+    pub method: Method,
+    pub url: String,
+    /// JSON pointer to the port value in the response, ie. `/config/port`
+    pub pointer: String,
+}
+
+/// Sends a configured request with `{port}` replaced by the forwarded port
+pub struct Webhook {
+    pub client: Client,
+    pub protocol: Protocol,
+    pub hostname: String,
+    pub port: u16,
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub verify: Option<WebhookVerify>,
+    pub interval: Duration,
+}
+
+impl App for Webhook {
+    fn login(&self) -> Result<()> {
+        debug!("Webhook does not require login");
+        Ok(())
+    }
+
+    fn set_port(&self, port: u16) -> Result<()> {
+        let mut request = self.request(self.method.clone(), self.url.as_str(), port);
+        if let Some(body) = &self.body {
+            request = request.body(substitute_port(body, port));
+        }
+        let response = check_status("Webhook", request.send()?)?;
+        trace!("Webhook response: {:?}", response);
+
+        match &self.verify {
+            Some(verify) => {
+                let actual_port = self.get_current_port(verify, port)?;
+                debug!("actual_port: {:?}", actual_port);
+                if port == actual_port {
+                    info!("Port updated to {}", port);
+                    Ok(())
+                } else {
+                    Err(PortUpdate(format!(
+                        "Actual port {} does not match expected port number {}",
+                        actual_port, port
+                    )))
+                }
+            }
+            None => {
+                info!("Port updated to {}", port);
+                Ok(())
+            }
+        }
+    }
+
+    fn current_port(&self, port: u16) -> Result<Option<u16>> {
+        match &self.verify {
+            Some(verify) => Ok(Some(self.get_current_port(verify, port)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Webhook {
+    /// Builds a request for the URL template, a template starting with `/` is relative to the host
+    fn request(&self, method: Method, url: &str, port: u16) -> RequestBuilder {
+        let url = substitute_port(url, port);
+        let url = match url.starts_with('/') {
+            true => endpoint(
+                self.protocol,
+                self.hostname.as_str(),
+                self.port,
+                url.as_str(),
+            ),
+            false => url,
+        };
+        self.headers.iter().fold(
+            self.client.request(method, url),
+            |request, (name, value)| request.header(name.as_str(), substitute_port(value, port)),
+        )
+    }
+
+    fn get_current_port(&self, verify: &WebhookVerify, port: u16) -> Result<u16> {
+        let response = check_status(
+            "Webhook",
+            self.request(verify.method.clone(), verify.url.as_str(), port)
+                .send()?,
+        )?;
+        let json: Value = response.json()?;
+        trace!("Webhook verify response json value: {}", json);
+        let value = json.pointer(verify.pointer.as_str()).ok_or_else(|| {
+            ParsingFailure(format!(
+                "Webhook verify response has no value at {}",
+                verify.pointer
+            ))
+        })?;
+        match value {
+            Value::Number(number) => number
+                .as_u64()
+                .and_then(|v| u16::try_from(v).ok())
+                .ok_or_else(|| ParsingFailure(format!("{number} is not a valid port"))),
+            Value::String(string) => Ok(string.trim().parse::<u16>()?),
+            _ => Err(ParsingFailure(format!("{value} is not a valid port"))),
+        }
+    }
+}
+
+/// Parses one `Name: value` header per line
+pub fn parse_headers(value: &str) -> Result<Vec<(String, String)>> {
+    value
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.split_once(HEADER_SEPARATOR)
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .ok_or_else(|| {
+                    ParsingFailure(format!("Header {line} is not in Name: value format"))
+                })
+        })
+        .collect()
+}
+
+pub fn parse_method(value: &str) -> Result<Method> {
+    Method::from_bytes(value.trim().to_uppercase().as_bytes())
+        .map_err(|_| ParsingFailure(format!("{value} is not a valid HTTP method")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::{GET, PUT};
+    use serde_json::json;
+
+    #[test]
+    fn set_port_with_verify() {
+        const PORT: u16 = 27015;
+        let server = MockServer::start();
+        let update = server.mock(|when, then| {
+            when.method(PUT)
+                .path("/api/servers/1/port/27015")
+                .header("authorization", "Bearer token")
+                .header("x-port", "27015")
+                .body(r#"{"port":27015}"#);
+            then.status(204);
+        });
+        let verify = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/servers/1")
+                .header("authorization", "Bearer token");
+            then.status(200)
+                .json_body(json!({"server": {"port": "27015"}}));
+        });
+
+        let app = Webhook {
+            client: Default::default(),
+            protocol: Default::default(),
+            hostname: server.host(),
+            port: server.port(),
+            method: parse_method("put").unwrap(),
+            url: "/api/servers/1/port/{port}".into(),
+            headers: parse_headers("Authorization: Bearer token\nX-Port: {port}\n").unwrap(),
+            body: Some(r#"{"port":{port}}"#.into()),
+            verify: Some(WebhookVerify {
+                method: Method::GET,
+                url: server.url("/api/servers/1"),
+                pointer: "/server/port".into(),
+            }),
+            interval: Default::default(),
+        };
+        let result = app.set_port(PORT);
+        update.assert();
+        verify.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn headers() {
+        assert_eq!(
+            parse_headers("X-Api-Key: abc:def\n\n  Accept: application/json").unwrap(),
+            vec![
+                ("X-Api-Key".to_string(), "abc:def".to_string()),
+                ("Accept".to_string(), "application/json".to_string())
+            ]
+        );
+        assert!(parse_headers("X-Api-Key abc").is_err());
+    }
+}
@@ -0,0 +1,469 @@
+use crate::LOG_LEVEL;
+use crate::apps::{
+    self, BIND_CHECK_ONLY, CHECK_INTERVAL, COMMAND_TIMEOUT, DISABLE_PORT_MAPPING, PAUSE_AFTER,
+    PORT, REANNOUNCE, RECONCILE_INTERVAL,
+};
+use crate::error::Error::ParsingFailure;
+use crate::error::Result;
+use crate::sources::{self, NATPMP_LIFETIME, PORT_FORWARD_MAX_AGE};
+use crate::targets::{TARGETS, target_prefix};
+use indexmap::IndexMap;
+use serde::de::{DeserializeSeed, Error as _, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::env::VarError;
+use std::fmt::Formatter;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::str::FromStr;
+use tracing_subscriber::filter::LevelFilter;
+
+const CONFIG_ARGUMENT: &str = "--config";
+const SECRET_FILE_SUFFIX: &str = "_FILE";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Layout of the configuration file, values are checked while the file is read so errors have their line number
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default, deserialize_with = "log_level")]
+    log_level: Option<String>,
+    check_interval: Option<u64>,
+    reconcile_interval: Option<u64>,
+    #[serde(default)]
+    source: Variables<SourceTable>,
+    #[serde(default)]
+    targets: IndexMap<String, Variables<TargetTable>>,
+}
+
+/// Variables a table of the configuration file accepts
+trait Table {
+    const VARIABLES: &'static [&'static str];
+    const SECRETS: &'static [&'static str];
+}
+
+#[derive(Debug, Default)]
+struct SourceTable;
+
+impl Table for SourceTable {
+    const VARIABLES: &'static [&'static str] = sources::VARIABLES;
+    const SECRETS: &'static [&'static str] = sources::SECRETS;
+}
+
+#[derive(Debug, Default)]
+struct TargetTable;
+
+impl Table for TargetTable {
+    const VARIABLES: &'static [&'static str] = apps::VARIABLES;
+    const SECRETS: &'static [&'static str] = apps::SECRETS;
+}
+
+/// Values of a table by variable name, ie. `port_source` is `PORT_SOURCE`
+#[derive(Debug)]
+struct Variables<T> {
+    values: HashMap<String, String>,
+    table: PhantomData<T>,
+}
+
+impl<T> Default for Variables<T> {
+    fn default() -> Self {
+        Self {
+            values: HashMap::new(),
+            table: PhantomData,
+        }
+    }
+}
+
+impl<'de, T: Table> Deserialize<'de> for Variables<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_map(VariablesVisitor(PhantomData))
+    }
+}
+
+struct VariablesVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Table> Visitor<'de> for VariablesVisitor<T> {
+    type Value = Variables<T>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a table of variables")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let mut variables = Variables::default();
+        while let Some((key, name)) = map.next_key_seed(KeySeed::<T>(PhantomData))? {
+            let value = map.next_value_seed(ValueSeed {
+                key: key.as_str(),
+                name: name.as_str(),
+            })?;
+            if variables.values.insert(name, value).is_some() {
+                return Err(A::Error::custom(format!("{key} is defined more than once")));
+            }
+        }
+        Ok(variables)
+    }
+}
+
+/// Checks the key while it is read so the error has the position of the key
+struct KeySeed<T>(PhantomData<T>);
+
+impl<'de, T: Table> DeserializeSeed<'de> for KeySeed<T> {
+    /// The key and its variable name
+    type Value = (String, String);
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        let name = variable(key.as_str(), T::VARIABLES, T::SECRETS)
+            .ok_or_else(|| D::Error::custom(format!("{key} is not a valid key")))?;
+        Ok((key, name))
+    }
+}
+
+/// Checks the value while it is read so the error has the position of the value
+struct ValueSeed<'a> {
+    key: &'a str,
+    name: &'a str,
+}
+
+impl<'de> DeserializeSeed<'de> for ValueSeed<'_> {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        let Scalar(value) = Scalar::deserialize(deserializer)?;
+        validate(self.name, value.as_str()).map_err(|e| {
+            D::Error::custom(format!("{} value {value} is not valid -> {e}", self.key))
+        })?;
+        Ok(value)
+    }
+}
+
+/// String, number or boolean value kept as the string an environment variable would have
+struct Scalar(String);
+
+impl<'de> Deserialize<'de> for Scalar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(ScalarVisitor)
+    }
+}
+
+struct ScalarVisitor;
+
+impl Visitor<'_> for ScalarVisitor {
+    type Value = Scalar;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a string, number or boolean")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> std::result::Result<Self::Value, E> {
+        Ok(Scalar(value.to_string()))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> std::result::Result<Self::Value, E> {
+        Ok(Scalar(value.to_string()))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> std::result::Result<Self::Value, E> {
+        Ok(Scalar(value.to_string()))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> std::result::Result<Self::Value, E> {
+        Ok(Scalar(value.to_string()))
+    }
+
+    fn visit_str<E>(self, value: &str) -> std::result::Result<Self::Value, E> {
+        Ok(Scalar(value.to_string()))
+    }
+}
+
+fn log_level<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<String>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    LevelFilter::from_str(value.as_str())
+        .map_err(|e| D::Error::custom(format!("log_level value {value} is not valid -> {e}")))?;
+    Ok(Some(value))
+}
+
+/// Values from the configuration file by environment variable name, ie. `targets.qbit.host` is `QBIT_HOST`
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    values: HashMap<String, String>,
+}
+
+impl Config {
+    /// Reads a TOML or YAML configuration file depending on the extension
+    pub fn load(path: &Path) -> Result<Self> {
+        let input = std::fs::read_to_string(path)?;
+        let file = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => parse_toml(input.as_str()),
+            Some("yaml" | "yml") => parse_yaml(input.as_str()),
+            _ => {
+                return Err(ParsingFailure(format!(
+                    "{} is not a .toml, .yaml or .yml file",
+                    path.display()
+                )));
+            }
+        };
+        file.map(Self::from_file)
+            .map_err(|e| ParsingFailure(format!("{}:{}: {}", path.display(), e.line, e.message)))
+    }
+
+    fn from_file(file: ConfigFile) -> Self {
+        let mut values = file.source.values;
+        for (name, value) in [
+            (LOG_LEVEL, file.log_level),
+            (CHECK_INTERVAL, file.check_interval.map(|v| v.to_string())),
+            (
+                RECONCILE_INTERVAL,
+                file.reconcile_interval.map(|v| v.to_string()),
+            ),
+        ] {
+            if let Some(value) = value {
+                values.insert(name.to_string(), value);
+            }
+        }
+        for (target, variables) in file.targets.iter() {
+            let prefix = target_prefix(target);
+            for (name, value) in variables.values.iter() {
+                values.insert(format!("{prefix}{name}"), value.clone());
+            }
+        }
+        if !file.targets.is_empty() {
+            let names: Vec<&str> = file.targets.keys().map(String::as_str).collect();
+            values.insert(TARGETS.to_string(), names.join(","));
+        }
+        Self { values }
+    }
+
+    /// Reads the environment variable and falls back to the configuration file
+    pub fn var(&self, name: &str) -> std::result::Result<String, VarError> {
+        std::env::var(name).or_else(|error| self.values.get(name).cloned().ok_or(error))
+    }
+
+    /// Reads the prefixed variable, ie. `QBIT_HOST`, and falls back to the variable without the prefix
+    pub fn target_var(&self, prefix: &str, name: &str) -> std::result::Result<String, VarError> {
+        self.var(format!("{prefix}{name}").as_str())
+            .or_else(|_| self.var(name))
+    }
+
+    /// Reads the secret, or the file in the `_FILE` variable, ie. `PASSWORD_FILE`, with the prefixed variables first
+    pub fn target_secret(&self, prefix: &str, name: &str) -> Result<Option<String>> {
+        for name in [format!("{prefix}{name}"), name.to_string()] {
+            if let Ok(value) = self.var(name.as_str()) {
+                return Ok(Some(value));
+            }
+            if let Ok(path) = self.var(format!("{name}{SECRET_FILE_SUFFIX}").as_str()) {
+                let value = std::fs::read_to_string(path.as_str()).map_err(|e| {
+                    ParsingFailure(format!("{name}{SECRET_FILE_SUFFIX} {path} -> {e}"))
+                })?;
+                return Ok(Some(value.trim().to_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn secret(&self, name: &str) -> Result<Option<String>> {
+        self.target_secret("", name)
+    }
+}
+
+/// Loads the file given with `--config`, the configuration is empty without it
+pub fn config_init() -> Result<Config> {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        path = match arg.split_once('=') {
+            Some((CONFIG_ARGUMENT, value)) => Some(value.to_string()),
+            _ if arg == CONFIG_ARGUMENT => Some(args.next().ok_or_else(|| {
+                ParsingFailure(format!("{CONFIG_ARGUMENT} requires a file path"))
+            })?),
+            _ => return Err(ParsingFailure(format!("{arg} is not a valid argument"))),
+        };
+    }
+    match path {
+        Some(path) => Config::load(Path::new(path.as_str())),
+        None => Ok(Config::default()),
+    }
+}
+
+/// Parses the value of a variable and reports the variable name when it is not valid
+pub fn parse_var<T>(name: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse::<T>()
+        .map_err(|e| ParsingFailure(format!("{name} value {value} is not valid -> {e}")))
+}
+
+fn parse_toml(input: &str) -> std::result::Result<ConfigFile, SyntaxError> {
+    toml::from_str(input).map_err(|e| SyntaxError {
+        line: e
+            .span()
+            .map_or(0, |span| input[..span.start].matches('\n').count() + 1),
+        message: e.message().trim_end().to_string(),
+    })
+}
+
+fn parse_yaml(input: &str) -> std::result::Result<ConfigFile, SyntaxError> {
+    // An empty document or one with only comments has no mapping to read
+    if input
+        .lines()
+        .all(|line| line.trim().is_empty() || line.trim_start().starts_with('#'))
+    {
+        return Ok(ConfigFile::default());
+    }
+    serde_yaml::from_str(input).map_err(|e| {
+        let message = e.to_string();
+        match e.location() {
+            Some(location) => SyntaxError {
+                line: location.line(),
+                message: message
+                    .trim_end_matches(
+                        format!(" at line {} column {}", location.line(), location.column())
+                            .as_str(),
+                    )
+                    .to_string(),
+            },
+            None => SyntaxError { line: 0, message },
+        }
+    })
+}
+
+/// Returns the variable name for the lower case key when it is in the list, or the `_FILE` variable of a secret
+fn variable(key: &str, variables: &[&str], secrets: &[&str]) -> Option<String> {
+    let key = key.to_ascii_uppercase();
+    match key.strip_suffix(SECRET_FILE_SUFFIX) {
+        Some(secret) if secrets.contains(&secret) => Some(key),
+        _ => variables.contains(&key.as_str()).then_some(key),
+    }
+}
+
+fn validate(name: &str, value: &str) -> std::result::Result<(), String> {
+    match name {
+        PORT => value.parse::<u16>().map(drop).map_err(|e| e.to_string()),
+        CHECK_INTERVAL | RECONCILE_INTERVAL | PAUSE_AFTER | PORT_FORWARD_MAX_AGE
+        | COMMAND_TIMEOUT => value.parse::<u64>().map(drop).map_err(|e| e.to_string()),
+        REANNOUNCE | BIND_CHECK_ONLY | DISABLE_PORT_MAPPING => {
+            value.parse::<bool>().map(drop).map_err(|e| e.to_string())
+        }
+        NATPMP_LIFETIME => value.parse::<u32>().map(drop).map_err(|e| e.to_string()),
+        LOG_LEVEL => LevelFilter::from_str(value)
+            .map(drop)
+            .map_err(|e| e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variables() {
+        const TOML: &str = r#"
+log_level = "debug"
+check_interval = 10
+
+[source]
+port_source = "gluetun"
+gluetun_url = "http://gluetun:8000"
+
+[targets.media-qbit]
+application = "qBittorrent"
+port = 8080
+
+[targets.slskd]
+application = "slskd"
+"#;
+        let config = Config::from_file(parse_toml(TOML).unwrap());
+        assert_eq!(config.values["LOG_LEVEL"], "debug");
+        assert_eq!(config.values["GLUETUN_URL"], "http://gluetun:8000");
+        assert_eq!(config.values["MEDIA_QBIT_PORT"], "8080");
+        assert_eq!(config.values["SLSKD_APPLICATION"], "slskd");
+        assert_eq!(config.values["TARGETS"], "media-qbit,slskd");
+        assert_eq!(config.target_var("SLSKD_", CHECK_INTERVAL).unwrap(), "10");
+    }
+
+    #[test]
+    fn invalid_keys() {
+        let error = |input: &str| parse_yaml(input).unwrap_err().line;
+        assert_eq!(error("log_level: info\nhost: qbit"), 2);
+        assert_eq!(error("targets:\n  qbit:\n    port: 80800"), 3);
+        assert_eq!(error("source:\n  natpmp_lifetime: -1"), 2);
+        assert_eq!(error("targets:\n  qbit:\n    port_source: file"), 3);
+        assert_eq!(error("targets:\n  qbit:\n    host_file: /host"), 3);
+        assert_eq!(error("log_level: loud"), 1);
+
+        let error = |input: &str| parse_toml(input).unwrap_err().line;
+        assert_eq!(error("log_level = \"info\"\nhost = \"qbit\""), 2);
+        assert_eq!(
+            error("[targets.qbit]\napplication = \"qBittorrent\"\nport = 80800"),
+            3
+        );
+        assert_eq!(error("check_interval = -1"), 1);
+        assert_eq!(error("[source]\nport_source = [\"file\"]"), 2);
+    }
+
+    #[test]
+    fn secret_files() {
+        let path = std::env::temp_dir().join(format!("password_{}", std::process::id()));
+        std::fs::write(path.as_path(), "secret\n").unwrap();
+        let yaml = format!(
+            "targets:\n  qbit:\n    password_file: {0}\n    api_key: key\n  hook:\n    webhook_headers_file: {0}",
+            path.display()
+        );
+        let config = Config::from_file(parse_yaml(yaml.as_str()).unwrap());
+        let password = config.target_secret("QBIT_", "PASSWORD");
+        let headers = config.target_secret("HOOK_", "WEBHOOK_HEADERS");
+        std::fs::remove_file(path.as_path()).unwrap();
+
+        assert_eq!(password.unwrap().unwrap(), "secret");
+        assert_eq!(headers.unwrap().unwrap(), "secret");
+        assert_eq!(
+            config.target_secret("QBIT_", "API_KEY").unwrap().unwrap(),
+            "key"
+        );
+        assert_eq!(config.target_secret("SLSKD_", "API_KEY").unwrap(), None);
+        assert!(config.target_secret("QBIT_", "PASSWORD").is_err());
+    }
+
+    #[test]
+    fn anchors_and_inline_tables() {
+        const YAML: &str = r#"
+defaults: &defaults
+  application: qBittorrent
+  password: secret
+"#;
+        // Anchors are resolved but unknown top level keys are still rejected
+        assert!(parse_yaml(YAML).is_err());
+        const TARGETS: &str = r#"
+targets:
+  qbit: &qbit {application: qBittorrent, password: secret}
+  qbit2: *qbit
+"#;
+        let config = Config::from_file(parse_yaml(TARGETS).unwrap());
+        assert_eq!(config.values["QBIT2_PASSWORD"], "secret");
+        assert_eq!(config.values["TARGETS"], "qbit,qbit2");
+
+        let toml =
+            parse_toml("targets = { qbit = { application = \"qBittorrent\", port = 8080 } }");
+        assert_eq!(Config::from_file(toml.unwrap()).values["QBIT_PORT"], "8080");
+    }
+}
@@ -1,3 +1,4 @@
+use reqwest::StatusCode;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -7,6 +8,9 @@ pub enum Error {
     #[error("Authorization request unsuccessful")]
     Authorization,
 
+    #[error("Session is no longer valid: {0}")]
+    Session(String),
+
     #[error("Unsuccessful response: {0}")]
     AppResponse(String),
 
@@ -19,12 +23,37 @@ pub enum Error {
     #[error("Port update unsuccessful: {0}")]
     PortUpdate(String),
 
+    #[error("Interface binding is not in place: {0}")]
+    Binding(String),
+
     #[error("IO Error: {0}")]
     IO(#[from] std::io::Error),
 
     #[error("Parse Integer Error: {0}")]
     ParseInt(#[from] std::num::ParseIntError),
 
+    #[error("XML Error: {0}")]
+    Xml(#[from] quick_xml::Error),
+
+    #[error("File Watcher Error: {0}")]
+    Notify(#[from] notify::Error),
+
+    #[error("TLS Error: {0}")]
+    Tls(#[from] rustls::Error),
+
     #[error("Reqwest Error: {0:?}")]
     Reqwest(#[from] reqwest::Error),
 }
+
+impl Error {
+    /// Returns true when the credentials or the session were rejected so logging in again can succeed
+    pub fn is_authorization(&self) -> bool {
+        match self {
+            Error::Authorization | Error::Session(_) => true,
+            Error::Reqwest(error) => error.status().is_some_and(|status| {
+                status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+            }),
+            _ => false,
+        }
+    }
+}
@@ -1,62 +1,67 @@
-use crate::apps::{app_init, result_to_bool};
+use crate::config::{Config, config_init, parse_var};
 use crate::error::Result;
-use std::str::FromStr;
-use tracing::{error, trace};
+use crate::sources::source_init;
+use crate::targets::{next_update, port_unavailable, targets_init, update_targets};
+use std::time::Instant;
+use tracing::error;
 use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::fmt::time::LocalTime;
 
 mod apps;
+mod config;
 mod error;
+mod rencode;
 mod rpc;
+mod scgi;
+mod sources;
+mod targets;
+mod watcher;
+mod xmlrpc;
 
 const LINE_FEED: char = '\n';
 const LOG_LEVEL: &str = "LOG_LEVEL";
 
 fn main() {
+    let config = config_init().and_then(|config| Ok((log_level(&config)?, config)));
     tracing_subscriber::fmt()
         .with_timer(LocalTime::rfc_3339())
-        .with_max_level(log_level())
+        .with_max_level(match &config {
+            Ok((level, _)) => *level,
+            Err(_) => LevelFilter::INFO,
+        })
         .init();
-    if let Err(error) = run() {
+    if let Err(error) = config.and_then(|(_, config)| run(&config)) {
         error!("{error}")
     }
 }
 
-fn run() -> Result<()> {
-    let app = app_init()?;
-    let mut last_port = 0;
-    let mut logged_in = result_to_bool(app.login());
-
-    if !logged_in {
-        app.wait()
-    }
+fn run(config: &Config) -> Result<()> {
+    let mut targets = targets_init(config)?;
+    let source = source_init(config)?;
 
+    let mut unavailable_since: Option<Instant> = None;
+    let mut last_port = None;
     loop {
-        if logged_in {
-            match app.check_port_forward() {
-                Ok(port) => {
-                    if last_port.ne(&port) {
-                        if result_to_bool(app.set_port(port)) {
-                            last_port = port;
-                        }
-                    } else {
-                        trace!("Current and previous port match. No update required.")
-                    }
-                }
-                Err(error) => {
-                    error!("Unable to get port value: {}", error)
-                }
+        match source.forwarded_port() {
+            Ok(port) => {
+                unavailable_since = None;
+                let changed = last_port.replace(port) != Some(port);
+                update_targets(&mut targets, port, changed)
+            }
+            Err(error) => {
+                error!("Unable to get port value: {}", error);
+                last_port = None;
+                let unavailable_for = unavailable_since.get_or_insert_with(Instant::now).elapsed();
+                port_unavailable(&mut targets, unavailable_for)
             }
-        } else {
-            logged_in = result_to_bool(app.login());
         }
-        app.wait()
+        source.wait(next_update(&targets))
     }
 }
 
-fn log_level() -> LevelFilter {
-    match std::env::var(LOG_LEVEL) {
-        Ok(v) => LevelFilter::from_str(v.as_str()).unwrap_or(LevelFilter::INFO),
-        Err(_) => LevelFilter::INFO,
+fn log_level(config: &Config) -> Result<LevelFilter> {
+    match config.var(LOG_LEVEL) {
+        Ok(value) => parse_var(LOG_LEVEL, value.as_str()),
+        Err(_) => Ok(LevelFilter::INFO),
     }
 }
@@ -0,0 +1,293 @@
+use crate::error::Error::ParsingFailure;
+use crate::error::Result;
+use serde_json::{Map, Number, Value};
+
+// Type codes used by the python rencode module
+const CHR_FLOAT64: u8 = 44;
+const CHR_LIST: u8 = 59;
+const CHR_DICT: u8 = 60;
+const CHR_INT: u8 = 61;
+const CHR_INT1: u8 = 62;
+const CHR_INT2: u8 = 63;
+const CHR_INT4: u8 = 64;
+const CHR_INT8: u8 = 65;
+const CHR_FLOAT32: u8 = 66;
+const CHR_TRUE: u8 = 67;
+const CHR_FALSE: u8 = 68;
+const CHR_NONE: u8 = 69;
+const CHR_TERM: u8 = 127;
+const STRING_SEPARATOR: u8 = b':';
+
+const INT_POS_FIXED_START: u8 = 0;
+const INT_POS_FIXED_COUNT: u8 = 44;
+const INT_NEG_FIXED_START: u8 = 70;
+const INT_NEG_FIXED_COUNT: u8 = 32;
+const DICT_FIXED_START: u8 = 102;
+const DICT_FIXED_COUNT: u8 = 25;
+const STR_FIXED_START: u8 = 128;
+const STR_FIXED_COUNT: u8 = 64;
+const LIST_FIXED_START: u8 = STR_FIXED_START + STR_FIXED_COUNT;
+const LIST_FIXED_COUNT: u8 = 64;
+
+/// Encodes a JSON value using rencode, arrays are encoded as lists
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    encode_value(value, &mut buffer);
+    buffer
+}
+
+/// Decodes a rencode value into JSON, strings must be UTF-8 and dictionary keys are converted to strings
+pub fn decode(data: &[u8]) -> Result<Value> {
+    let mut decoder = Decoder { data, position: 0 };
+    let value = decoder.value()?;
+    match decoder.position == data.len() {
+        true => Ok(value),
+        false => Err(ParsingFailure(format!(
+            "rencode data has {} trailing bytes",
+            data.len() - decoder.position
+        ))),
+    }
+}
+
+fn encode_value(value: &Value, buffer: &mut Vec<u8>) {
+    match value {
+        Value::Null => buffer.push(CHR_NONE),
+        Value::Bool(true) => buffer.push(CHR_TRUE),
+        Value::Bool(false) => buffer.push(CHR_FALSE),
+        Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(integer), _) => encode_integer(integer, buffer),
+            (None, Some(integer)) => {
+                buffer.push(CHR_INT);
+                buffer.extend_from_slice(integer.to_string().as_bytes());
+                buffer.push(CHR_TERM);
+            }
+            _ => {
+                buffer.push(CHR_FLOAT64);
+                buffer.extend_from_slice(&number.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        },
+        Value::String(string) => encode_string(string, buffer),
+        Value::Array(values) => {
+            if values.len() < LIST_FIXED_COUNT as usize {
+                buffer.push(LIST_FIXED_START + values.len() as u8);
+                values.iter().for_each(|v| encode_value(v, buffer));
+            } else {
+                buffer.push(CHR_LIST);
+                values.iter().for_each(|v| encode_value(v, buffer));
+                buffer.push(CHR_TERM);
+            }
+        }
+        Value::Object(map) => {
+            let fixed = map.len() < DICT_FIXED_COUNT as usize;
+            buffer.push(match fixed {
+                true => DICT_FIXED_START + map.len() as u8,
+                false => CHR_DICT,
+            });
+            for (key, value) in map {
+                encode_string(key, buffer);
+                encode_value(value, buffer);
+            }
+            if !fixed {
+                buffer.push(CHR_TERM);
+            }
+        }
+    }
+}
+
+fn encode_integer(integer: i64, buffer: &mut Vec<u8>) {
+    if (0..INT_POS_FIXED_COUNT as i64).contains(&integer) {
+        buffer.push(INT_POS_FIXED_START + integer as u8);
+    } else if (-(INT_NEG_FIXED_COUNT as i64)..0).contains(&integer) {
+        buffer.push((INT_NEG_FIXED_START as i64 - 1 - integer) as u8);
+    } else if let Ok(integer) = i8::try_from(integer) {
+        buffer.push(CHR_INT1);
+        buffer.extend_from_slice(&integer.to_be_bytes());
+    } else if let Ok(integer) = i16::try_from(integer) {
+        buffer.push(CHR_INT2);
+        buffer.extend_from_slice(&integer.to_be_bytes());
+    } else if let Ok(integer) = i32::try_from(integer) {
+        buffer.push(CHR_INT4);
+        buffer.extend_from_slice(&integer.to_be_bytes());
+    } else {
+        buffer.push(CHR_INT8);
+        buffer.extend_from_slice(&integer.to_be_bytes());
+    }
+}
+
+fn encode_string(string: &str, buffer: &mut Vec<u8>) {
+    if string.len() < STR_FIXED_COUNT as usize {
+        buffer.push(STR_FIXED_START + string.len() as u8);
+    } else {
+        buffer.extend_from_slice(string.len().to_string().as_bytes());
+        buffer.push(STRING_SEPARATOR);
+    }
+    buffer.extend_from_slice(string.as_bytes());
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Decoder<'_> {
+    fn value(&mut self) -> Result<Value> {
+        let code = self.take(1)?[0];
+        Ok(match code {
+            CHR_NONE => Value::Null,
+            CHR_TRUE => Value::Bool(true),
+            CHR_FALSE => Value::Bool(false),
+            CHR_INT1 => Value::from(i8::from_be_bytes(self.array()?)),
+            CHR_INT2 => Value::from(i16::from_be_bytes(self.array()?)),
+            CHR_INT4 => Value::from(i32::from_be_bytes(self.array()?)),
+            CHR_INT8 => Value::from(i64::from_be_bytes(self.array()?)),
+            CHR_INT => {
+                let digits = String::from_utf8_lossy(self.until(CHR_TERM)?).into_owned();
+                match digits.parse::<i64>() {
+                    Ok(integer) => Value::from(integer),
+                    Err(_) => Value::from(digits.parse::<u64>()?),
+                }
+            }
+            CHR_FLOAT32 => float(f32::from_be_bytes(self.array()?) as f64),
+            CHR_FLOAT64 => float(f64::from_be_bytes(self.array()?)),
+            CHR_LIST => {
+                let mut values = Vec::new();
+                while self.peek()? != CHR_TERM {
+                    values.push(self.value()?);
+                }
+                self.position += 1;
+                Value::Array(values)
+            }
+            CHR_DICT => {
+                let mut map = Map::new();
+                while self.peek()? != CHR_TERM {
+                    let (key, value) = self.entry()?;
+                    map.insert(key, value);
+                }
+                self.position += 1;
+                Value::Object(map)
+            }
+            b'0'..=b'9' => {
+                self.position -= 1;
+                let length =
+                    String::from_utf8_lossy(self.until(STRING_SEPARATOR)?).parse::<usize>()?;
+                string(self.take(length)?)?
+            }
+            c if c < INT_POS_FIXED_START + INT_POS_FIXED_COUNT => {
+                Value::from(c - INT_POS_FIXED_START)
+            }
+            c if (INT_NEG_FIXED_START..INT_NEG_FIXED_START + INT_NEG_FIXED_COUNT).contains(&c) => {
+                Value::from(INT_NEG_FIXED_START as i64 - 1 - c as i64)
+            }
+            c if (DICT_FIXED_START..DICT_FIXED_START + DICT_FIXED_COUNT).contains(&c) => {
+                let mut map = Map::new();
+                for _ in 0..c - DICT_FIXED_START {
+                    let (key, value) = self.entry()?;
+                    map.insert(key, value);
+                }
+                Value::Object(map)
+            }
+            c if (STR_FIXED_START..STR_FIXED_START + STR_FIXED_COUNT).contains(&c) => {
+                string(self.take((c - STR_FIXED_START) as usize)?)?
+            }
+            c if c >= LIST_FIXED_START => {
+                let mut values = Vec::new();
+                for _ in 0..c - LIST_FIXED_START {
+                    values.push(self.value()?);
+                }
+                Value::Array(values)
+            }
+            c => {
+                return Err(ParsingFailure(format!(
+                    "rencode type code {c} is not valid"
+                )));
+            }
+        })
+    }
+
+    fn entry(&mut self) -> Result<(String, Value)> {
+        let key = match self.value()? {
+            Value::String(key) => key,
+            key => key.to_string(),
+        };
+        Ok((key, self.value()?))
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.data
+            .get(self.position)
+            .copied()
+            .ok_or_else(|| ParsingFailure("rencode data ended unexpectedly".into()))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8]> {
+        let end = self.position + length;
+        let bytes = self
+            .data
+            .get(self.position..end)
+            .ok_or_else(|| ParsingFailure("rencode data ended unexpectedly".into()))?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// Returns the bytes up to the delimiter and skips past it
+    fn until(&mut self, delimiter: u8) -> Result<&[u8]> {
+        let length = self.data[self.position..]
+            .iter()
+            .position(|b| *b == delimiter)
+            .ok_or_else(|| ParsingFailure("rencode data ended unexpectedly".into()))?;
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length + 1;
+        Ok(bytes)
+    }
+}
+
+fn string(bytes: &[u8]) -> Result<Value> {
+    String::from_utf8(bytes.to_vec())
+        .map(Value::String)
+        .map_err(|e| ParsingFailure(format!("rencode string is not UTF-8 -> {e}")))
+}
+
+fn float(value: f64) -> Value {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn encode_values() {
+        assert_eq!(encode(&json!(1)), vec![1]);
+        assert_eq!(encode(&json!(-1)), vec![70]);
+        assert_eq!(encode(&json!(100)), vec![CHR_INT1, 100]);
+        assert_eq!(encode(&json!(58846)), vec![CHR_INT4, 0, 0, 0xe5, 0xde]);
+        assert_eq!(encode(&json!("abc")), vec![131, b'a', b'b', b'c']);
+        assert_eq!(encode(&json!([true, null])), vec![194, CHR_TRUE, CHR_NONE]);
+        assert_eq!(encode(&json!({"a": 1})), vec![103, 129, b'a', 1]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = json!([
+            1,
+            "core.set_config",
+            [{"listen_ports": [51413, 51413], "random_port": false}],
+            {},
+            -40000,
+            9_000_000_000i64,
+            1.5,
+            "x".repeat(100),
+            (0..70).collect::<Vec<_>>(),
+        ]);
+        assert_eq!(decode(encode(&value).as_slice()).unwrap(), value);
+    }
+}
@@ -4,8 +4,8 @@ use reqwest::blocking::Response;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::fmt::{Display, Formatter};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[allow(unused)]
 #[derive(Debug, Copy, Clone)]
 pub enum JsonRpcVersion {
     V1,
@@ -15,7 +15,7 @@ pub enum JsonRpcVersion {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum RpcId {
-    Number(u128),
+    Number(u64),
     String(String),
 }
 
@@ -41,6 +41,7 @@ pub struct RpcRequest {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RpcResponse {
     jsonrpc: Option<String>,
+    #[serde(default)]
     result: Value,
     error: Option<RpcError>,
     id: Option<RpcId>,
@@ -66,6 +67,10 @@ impl RpcRequest {
             id,
         }
     }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
 }
 
 #[allow(unused)]
@@ -105,6 +110,19 @@ impl RpcError {
     pub fn message(&self) -> &str {
         &self.message
     }
+
+    pub fn code(&self) -> i64 {
+        self.code
+    }
+}
+
+pub fn generate_id() -> RpcId {
+    RpcId::Number(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("SystemTime duration_since error")
+            .as_nanos() as u64,
+    )
 }
 
 #[cfg(test)]
@@ -115,6 +133,8 @@ mod tests {
     const RPC_REQ_V2: &str = r#"{"jsonrpc":"2.0","method":"confirmFruitPurchase","params":[["apple","orange","mangoes"],1.123],"id":"194521489"}"#;
     const RPC_REQ_V2_OBJ: &str =
         r#"{"jsonrpc":"2.0","method":"subtract","params":{"minuend":42,"subtrahend":23},"id":3}"#;
+    const RPC_RESP_V2_ERROR: &str =
+        r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":"1"}"#;
 
     #[test]
     fn rpc_request_v1() {
@@ -144,6 +164,13 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rpc_response_v2_error() {
+        let response: RpcResponse = serde_json::from_str(RPC_RESP_V2_ERROR).unwrap();
+        assert!(!response.is_success());
+        assert_eq!(response.error().map(|e| e.code()), Some(-32601));
+    }
+
     #[test]
     fn rpc_request_v2_params_named() {
         let request = RpcRequest::new(
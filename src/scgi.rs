@@ -0,0 +1,181 @@
+use crate::error::Error::{AppResponse, ParsingFailure};
+use crate::error::Result;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::trace;
+
+const UNIX_PREFIX: &str = "unix://";
+const HEADER_SEPARATOR: &[u8] = b"\r\n\r\n";
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Address of an SCGI server, either a unix socket path or a TCP `host:port`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScgiAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for ScgiAddress {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix(UNIX_PREFIX) {
+            Ok(ScgiAddress::Unix(path.into()))
+        } else if s.starts_with('/') {
+            Ok(ScgiAddress::Unix(s.into()))
+        } else if s
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+        {
+            Ok(ScgiAddress::Tcp(s.into()))
+        } else {
+            Err(ParsingFailure(format!(
+                "SCGI address {s} is not a unix socket path or host:port"
+            )))
+        }
+    }
+}
+
+impl ScgiAddress {
+    /// Sends the body as an SCGI request and returns the body of the response
+    pub fn send(&self, uri: &str, body: &[u8]) -> Result<Vec<u8>> {
+        let request = scgi_request(uri, body);
+        let response = match self {
+            ScgiAddress::Tcp(address) => {
+                let mut stream = TcpStream::connect(address.as_str())?;
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                exchange(&mut stream, request.as_slice())?
+            }
+            #[cfg(unix)]
+            ScgiAddress::Unix(path) => {
+                let mut stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                exchange(&mut stream, request.as_slice())?
+            }
+            #[cfg(not(unix))]
+            ScgiAddress::Unix(_) => {
+                return Err(AppResponse(
+                    "SCGI unix sockets are not supported on this platform".into(),
+                ));
+            }
+        };
+        response_body(response)
+    }
+}
+
+fn exchange<S: Read + Write>(stream: &mut S, request: &[u8]) -> Result<Vec<u8>> {
+    stream.write_all(request)?;
+    stream.flush()?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(response)
+}
+
+/// Builds the netstring encoded headers followed by the request body
+fn scgi_request(uri: &str, body: &[u8]) -> Vec<u8> {
+    let headers = [
+        ("CONTENT_LENGTH", body.len().to_string()),
+        ("SCGI", "1".to_string()),
+        ("REQUEST_METHOD", "POST".to_string()),
+        ("REQUEST_URI", uri.to_string()),
+    ]
+    .iter()
+    .fold(Vec::new(), |mut headers, (name, value)| {
+        headers.extend_from_slice(name.as_bytes());
+        headers.push(0);
+        headers.extend_from_slice(value.as_bytes());
+        headers.push(0);
+        headers
+    });
+
+    let mut request = format!("{}:", headers.len()).into_bytes();
+    request.extend_from_slice(headers.as_slice());
+    request.push(b',');
+    request.extend_from_slice(body);
+    request
+}
+
+/// Strips the CGI style headers from the response and checks the status if one was sent
+fn response_body(response: Vec<u8>) -> Result<Vec<u8>> {
+    let position = response
+        .windows(HEADER_SEPARATOR.len())
+        .position(|w| w == HEADER_SEPARATOR)
+        .ok_or_else(|| ParsingFailure("SCGI response does not contain headers".into()))?;
+    let headers = String::from_utf8_lossy(&response[..position]);
+    trace!("SCGI response headers: {}", headers);
+
+    let status = headers
+        .lines()
+        .find_map(|line| line.strip_prefix("Status:"))
+        .map(|status| status.trim().to_string());
+    match status {
+        Some(status) if !status.starts_with('2') => Err(AppResponse(format!(
+            "SCGI request failed with status: {status}"
+        ))),
+        _ => Ok(response[position + HEADER_SEPARATOR.len()..].to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::net::TcpListener;
+
+    #[test]
+    fn scgi_address() {
+        assert_eq!(
+            ScgiAddress::from_str("/config/rtorrent.sock").unwrap(),
+            ScgiAddress::Unix("/config/rtorrent.sock".into())
+        );
+        assert_eq!(
+            ScgiAddress::from_str("unix:///config/rtorrent.sock").unwrap(),
+            ScgiAddress::Unix("/config/rtorrent.sock".into())
+        );
+        assert_eq!(
+            ScgiAddress::from_str("localhost:5000").unwrap(),
+            ScgiAddress::Tcp("localhost:5000".into())
+        );
+        assert!(ScgiAddress::from_str("localhost").is_err());
+    }
+
+    #[test]
+    fn send_tcp() {
+        const BODY: &[u8] = b"<methodCall/>";
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = ScgiAddress::Tcp(listener.local_addr().unwrap().to_string());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = Vec::new();
+            reader.read_until(b':', &mut length).unwrap();
+            let length: usize = String::from_utf8_lossy(&length[..length.len() - 1])
+                .parse()
+                .unwrap();
+            let mut headers = vec![0; length + 1];
+            reader.read_exact(&mut headers).unwrap();
+            let mut body = vec![0; BODY.len()];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"Status: 200 OK\r\nContent-Type: text/xml\r\n\r\n<methodResponse/>")
+                .unwrap();
+            (headers, body)
+        });
+
+        let response = address.send("/RPC2", BODY).unwrap();
+        let (headers, body) = server.join().unwrap();
+        assert_eq!(response, b"<methodResponse/>");
+        assert_eq!(body, BODY);
+        assert!(headers.starts_with(b"CONTENT_LENGTH\x0013\x00SCGI\x001\x00"));
+        assert_eq!(headers.last(), Some(&b','));
+    }
+}
@@ -0,0 +1,293 @@
+mod file;
+mod gluetun;
+mod natpmp;
+mod pia;
+
+use crate::apps::Protocol;
+use crate::config::{Config, parse_var};
+use crate::error::Error::ParsingFailure;
+use crate::error::Result;
+use crate::watcher::Watcher;
+use reqwest::Certificate;
+use reqwest::blocking::Client;
+use std::cell::RefCell;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+use strum::{Display, EnumString};
+use tracing::{debug, error, warn};
+
+// Environment Variables
+const PORT_SOURCE: &str = "PORT_SOURCE";
+const PORT_FORWARD_PATH: &str = "PORT_FORWARD_PATH";
+pub const PORT_FORWARD_MAX_AGE: &str = "PORT_FORWARD_MAX_AGE";
+const GLUETUN_URL: &str = "GLUETUN_URL";
+const GLUETUN_API_KEY: &str = "GLUETUN_API_KEY";
+const GLUETUN_USER: &str = "GLUETUN_USER";
+const GLUETUN_PASSWORD: &str = "GLUETUN_PASSWORD";
+const NATPMP_GATEWAY: &str = "NATPMP_GATEWAY";
+pub const NATPMP_LIFETIME: &str = "NATPMP_LIFETIME";
+const PIA_HOSTNAME: &str = "PIA_HOSTNAME";
+const PIA_GATEWAY: &str = "PIA_GATEWAY";
+const PIA_TOKEN: &str = "PIA_TOKEN";
+const PIA_CA_CERT: &str = "PIA_CA_CERT";
+const PIA_SIGNATURE_PATH: &str = "PIA_SIGNATURE_PATH";
+
+/// Variables that configure the port source
+pub const VARIABLES: &[&str] = &[
+    PORT_SOURCE,
+    PORT_FORWARD_PATH,
+    PORT_FORWARD_MAX_AGE,
+    GLUETUN_URL,
+    GLUETUN_API_KEY,
+    GLUETUN_USER,
+    GLUETUN_PASSWORD,
+    NATPMP_GATEWAY,
+    NATPMP_LIFETIME,
+    PIA_HOSTNAME,
+    PIA_GATEWAY,
+    PIA_TOKEN,
+    PIA_CA_CERT,
+    PIA_SIGNATURE_PATH,
+];
+
+/// Variables that can also be read from the file in the `_FILE` variable, ie. `GLUETUN_PASSWORD_FILE`
+pub const SECRETS: &[&str] = &[GLUETUN_API_KEY, GLUETUN_USER, GLUETUN_PASSWORD, PIA_TOKEN];
+
+// Defaults
+const PORT_FORWARD_PATH_DEFAULT: &str = "/tmp/gluetun/forwarded_port";
+const GLUETUN_URL_DEFAULT: &str = "http://localhost:8000";
+const NATPMP_GATEWAY_DEFAULT: &str = "10.2.0.1";
+const NATPMP_LIFETIME_DEFAULT: u32 = 60;
+const PIA_SIGNATURE_PATH_DEFAULT: &str = "/tmp/pia/signature.json";
+
+#[derive(Debug, Eq, PartialEq, Default, Clone, Copy, Display, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum Source {
+    #[default]
+    File,
+    Gluetun,
+    NatPmp,
+    Pia,
+}
+
+pub trait PortSource {
+    /// Returns the port currently forwarded by the VPN provider
+    fn forwarded_port(&self) -> Result<u16>;
+
+    /// Waits until the port should be checked again
+    fn wait(&self, interval: Duration) {
+        sleep(interval);
+    }
+}
+
+/// Builds the port source again so credentials are re-read, ie. after a secret file was rotated
+pub type Reload = Box<dyn Fn() -> Result<Box<dyn PortSource>>>;
+
+/// Port source that is built again with the current credentials when they are rejected
+struct Reloading {
+    source: RefCell<Box<dyn PortSource>>,
+    reload: Reload,
+}
+
+impl PortSource for Reloading {
+    fn forwarded_port(&self) -> Result<u16> {
+        let result = self.source.borrow().forwarded_port();
+        match result {
+            Err(error) if error.is_authorization() => {
+                error!("{error}");
+                match (self.reload)() {
+                    Ok(source) => {
+                        debug!("Reloaded source credentials");
+                        self.source.replace(source);
+                        self.source.borrow().forwarded_port()
+                    }
+                    Err(reload_error) => {
+                        error!("Unable to reload source credentials -> {reload_error}");
+                        Err(error)
+                    }
+                }
+            }
+            result => result,
+        }
+    }
+
+    fn wait(&self, interval: Duration) {
+        self.source.borrow().wait(interval)
+    }
+}
+
+/// Builds the port source with a reload that reads the variables and secret files again
+pub fn source_init(config: &Config) -> Result<Box<dyn PortSource>> {
+    let source = port_source(config)?;
+    let config = config.clone();
+    Ok(Box::new(Reloading {
+        source: RefCell::new(source),
+        reload: Box::new(move || port_source(&config)),
+    }))
+}
+
+fn port_source(config: &Config) -> Result<Box<dyn PortSource>> {
+    let source = match config.var(PORT_SOURCE) {
+        Ok(value) => Source::from_str(value.as_str())
+            .map_err(|_| ParsingFailure(format!("{PORT_SOURCE} value is not valid source type")))?,
+        Err(_) => Source::default(),
+    };
+    debug!("source: {}", source);
+
+    Ok(match source {
+        Source::File => {
+            let path: PathBuf = config
+                .var(PORT_FORWARD_PATH)
+                .unwrap_or(PORT_FORWARD_PATH_DEFAULT.into())
+                .into();
+            let max_age = match config.var(PORT_FORWARD_MAX_AGE) {
+                Ok(value) => parse_var(PORT_FORWARD_MAX_AGE, value.as_str())?,
+                Err(_) => 0,
+            };
+            debug!("port_forward_path: {:?}", path);
+            debug!("port_forward_max_age: {}", max_age);
+            let watcher = Watcher::new(path.as_path())
+                .inspect_err(|error| warn!("Polling {} -> {error}", path.display()))
+                .ok();
+            Box::new(file::PortFile {
+                path,
+                max_age: match max_age {
+                    0 => None,
+                    seconds => Some(Duration::from_secs(seconds)),
+                },
+                watcher,
+            })
+        }
+        Source::Gluetun => {
+            let url = config
+                .var(GLUETUN_URL)
+                .unwrap_or(GLUETUN_URL_DEFAULT.into());
+            debug!("gluetun_url: {}", url);
+            Box::new(gluetun::Gluetun {
+                client: Client::new(),
+                url: url.trim_end_matches('/').to_string(),
+                api_key: config.secret(GLUETUN_API_KEY)?.unwrap_or_default(),
+                username: config.secret(GLUETUN_USER)?.unwrap_or_default(),
+                password: config.secret(GLUETUN_PASSWORD)?.unwrap_or_default(),
+            })
+        }
+        Source::NatPmp => {
+            let gateway = natpmp::parse_gateway(
+                config
+                    .var(NATPMP_GATEWAY)
+                    .unwrap_or(NATPMP_GATEWAY_DEFAULT.into())
+                    .as_str(),
+            )?;
+            let lifetime = match config.var(NATPMP_LIFETIME) {
+                Ok(value) => parse_var(NATPMP_LIFETIME, value.as_str())?,
+                _ => NATPMP_LIFETIME_DEFAULT,
+            };
+            debug!("natpmp_gateway: {}", gateway);
+            debug!("natpmp_lifetime: {}", lifetime);
+            Box::new(natpmp::NatPmp {
+                gateway,
+                lifetime,
+                port: Default::default(),
+                renew_at: Default::default(),
+            })
+        }
+        Source::Pia => {
+            let hostname = config
+                .var(PIA_HOSTNAME)
+                .map_err(|_| ParsingFailure(format!("{PIA_HOSTNAME} is required for pia")))?;
+            let token = config
+                .secret(PIA_TOKEN)?
+                .ok_or_else(|| ParsingFailure(format!("{PIA_TOKEN} is required for pia")))?;
+            let signature_path = config
+                .var(PIA_SIGNATURE_PATH)
+                .unwrap_or(PIA_SIGNATURE_PATH_DEFAULT.into())
+                .into();
+            let mut client = Client::builder();
+            if let Ok(gateway) = config.var(PIA_GATEWAY) {
+                let gateway = gateway.parse::<IpAddr>().map_err(|e| {
+                    ParsingFailure(format!("{gateway} is not a valid gateway address -> {e}"))
+                })?;
+                debug!("pia_gateway: {}", gateway);
+                client = client.resolve(hostname.as_str(), SocketAddr::new(gateway, pia::PIA_PORT));
+            }
+            if let Ok(path) = config.var(PIA_CA_CERT) {
+                debug!("pia_ca_cert: {}", path);
+                client = client
+                    .add_root_certificate(Certificate::from_pem(std::fs::read(path)?.as_slice())?);
+            }
+            debug!("pia_hostname: {}", hostname);
+            debug!("pia_signature_path: {:?}", signature_path);
+            Box::new(pia::Pia {
+                client: client.build()?,
+                protocol: Protocol::Https,
+                hostname,
+                port: pia::PIA_PORT,
+                token,
+                signature_path,
+                signature: Default::default(),
+                bind_at: Default::default(),
+            })
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error::Authorization;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Returns the port, or an authorization error when the credentials are not valid
+    struct MockSource {
+        port: u16,
+        valid: bool,
+    }
+
+    impl PortSource for MockSource {
+        fn forwarded_port(&self) -> Result<u16> {
+            match self.valid {
+                true => Ok(self.port),
+                false => Err(Authorization),
+            }
+        }
+    }
+
+    #[test]
+    fn reload_after_authorization() {
+        let reloads = Rc::new(Cell::new(0));
+        let reload_count = reloads.clone();
+        let source = Reloading {
+            source: RefCell::new(Box::new(MockSource {
+                port: 51413,
+                valid: false,
+            })),
+            reload: Box::new(move || {
+                reload_count.set(reload_count.get() + 1);
+                Ok(Box::new(MockSource {
+                    port: 51414,
+                    valid: true,
+                }))
+            }),
+        };
+
+        assert_eq!(source.forwarded_port().unwrap(), 51414);
+        assert_eq!(source.forwarded_port().unwrap(), 51414);
+        assert_eq!(reloads.get(), 1);
+    }
+
+    #[test]
+    fn reload_failure_returns_authorization() {
+        let source = Reloading {
+            source: RefCell::new(Box::new(MockSource {
+                port: 51413,
+                valid: false,
+            })),
+            reload: Box::new(|| Err(ParsingFailure("GLUETUN_PASSWORD_FILE".into()))),
+        };
+        assert!(matches!(source.forwarded_port(), Err(Authorization)));
+    }
+}
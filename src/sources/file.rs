@@ -0,0 +1,111 @@
+use crate::LINE_FEED;
+use crate::error::Error::PortPath;
+use crate::error::Result;
+use crate::sources::PortSource;
+use crate::watcher::Watcher;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+use tracing::{debug, trace, warn};
+
+/// Reads the port from a file written by the VPN client, ie. the Gluetun `forwarded_port` file
+pub struct PortFile {
+    pub path: PathBuf,
+    /// Time since the file was last written before the port is treated as unavailable, never when not set
+    pub max_age: Option<Duration>,
+    /// Wakes up as soon as the file is written, the file is only polled when this is not set
+    pub watcher: Option<Watcher>,
+}
+
+impl PortSource for PortFile {
+    fn forwarded_port(&self) -> Result<u16> {
+        match self.path.try_exists()? {
+            true => {
+                if let Some(max_age) = self.max_age {
+                    let age = std::fs::metadata(self.path.as_path())?
+                        .modified()?
+                        .elapsed()
+                        .unwrap_or_default();
+                    if age > max_age {
+                        return Err(PortPath(format!(
+                            "Path {} was last written {} seconds ago",
+                            self.path.display(),
+                            age.as_secs()
+                        )));
+                    }
+                }
+                let value = std::fs::read_to_string(self.path.as_path())?;
+                trace!("Found port value {}", value);
+                let value = value.trim_matches(LINE_FEED);
+                Ok(value.parse::<u16>()?)
+            }
+            false => Err(PortPath(format!(
+                "Path {} does not exist",
+                self.path.display()
+            ))),
+        }
+    }
+
+    fn wait(&self, interval: Duration) {
+        match &self.watcher {
+            Some(watcher) => match watcher.wait(interval) {
+                Ok(true) => debug!("{} changed", self.path.display()),
+                Ok(false) => trace!("{} did not change", self.path.display()),
+                Err(error) => {
+                    warn!("Unable to watch {} -> {error}", self.path.display());
+                    sleep(interval)
+                }
+            },
+            None => sleep(interval),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn forwarded_port() {
+        let path = std::env::temp_dir().join(format!("forwarded_port_{}", std::process::id()));
+        let source = PortFile {
+            path: path.clone(),
+            max_age: None,
+            watcher: None,
+        };
+        assert!(matches!(source.forwarded_port(), Err(PortPath(_))));
+
+        std::fs::write(path.as_path(), "51413\n").unwrap();
+        let result = source.forwarded_port();
+        std::fs::remove_file(path.as_path()).unwrap();
+        assert_eq!(result.unwrap(), 51413);
+    }
+
+    #[test]
+    fn stale_file() {
+        let path =
+            std::env::temp_dir().join(format!("forwarded_port_stale_{}", std::process::id()));
+        std::fs::write(path.as_path(), "51413\n").unwrap();
+        let mut source = PortFile {
+            path: path.clone(),
+            max_age: Some(Duration::from_secs(60)),
+            watcher: None,
+        };
+        let fresh = source.forwarded_port();
+
+        std::fs::File::options()
+            .write(true)
+            .open(path.as_path())
+            .and_then(|file| file.set_modified(SystemTime::now() - Duration::from_secs(120)))
+            .unwrap();
+        let stale = source.forwarded_port();
+        source.max_age = None;
+        let unchecked = source.forwarded_port();
+        std::fs::remove_file(path.as_path()).unwrap();
+
+        assert_eq!(fresh.unwrap(), 51413);
+        assert!(matches!(stale, Err(PortPath(_))));
+        assert_eq!(unchecked.unwrap(), 51413);
+    }
+}
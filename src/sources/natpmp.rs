@@ -0,0 +1,233 @@
+use crate::error::Error::{AppResponse, ParsingFailure};
+use crate::error::Result;
+use crate::sources::PortSource;
+use std::cell::Cell;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, trace, warn};
+
+const NATPMP_PORT: u16 = 5351;
+const VERSION: u8 = 0;
+const UDP_OPCODE: u8 = 1;
+const TCP_OPCODE: u8 = 2;
+const RESPONSE_OPCODE: u8 = 128;
+const RESPONSE_LENGTH: usize = 16;
+/// The internal port is not used by VPN gateways like ProtonVPN which forward the public port as is
+const INTERNAL_PORT: u16 = 1;
+/// Initial response timeout, doubled after each attempt as described in RFC 6886
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const ATTEMPTS: u32 = 5;
+
+/// Requests UDP and TCP port mappings from a NAT-PMP gateway, ie. `10.2.0.1` for ProtonVPN
+pub struct NatPmp {
+    pub gateway: SocketAddr,
+    /// Requested mapping lifetime in seconds
+    pub lifetime: u32,
+    pub port: Cell<u16>,
+    pub renew_at: Cell<Option<Instant>>,
+}
+
+struct Mapping {
+    external_port: u16,
+    lifetime: u32,
+}
+
+impl PortSource for NatPmp {
+    fn forwarded_port(&self) -> Result<u16> {
+        let udp = self.map(UDP_OPCODE, self.port.get())?;
+        let tcp = self.map(TCP_OPCODE, udp.external_port)?;
+        if udp.external_port != tcp.external_port {
+            return Err(AppResponse(format!(
+                "NAT-PMP TCP mapping port {} does not match UDP mapping port {}",
+                tcp.external_port, udp.external_port
+            )));
+        }
+
+        // Renew halfway through the lease so a lost response does not let the mapping expire
+        let lifetime = udp.lifetime.min(tcp.lifetime);
+        self.renew_at.set(Some(
+            Instant::now() + Duration::from_secs(lifetime as u64 / 2),
+        ));
+        if self.port.replace(udp.external_port) != udp.external_port {
+            info!(
+                "NAT-PMP mapped port {} for {} seconds",
+                udp.external_port, lifetime
+            );
+        }
+        Ok(udp.external_port)
+    }
+
+    fn wait(&self, interval: Duration) {
+        // The renewal time only applies to the wait after the mapping so a failed renewal does not busy loop
+        let wait = match self.renew_at.take() {
+            Some(renew_at) => interval.min(renew_at.saturating_duration_since(Instant::now())),
+            None => interval,
+        };
+        trace!("NAT-PMP waiting {:?}", wait);
+        sleep(wait);
+    }
+}
+
+impl NatPmp {
+    fn map(&self, opcode: u8, external_port: u16) -> Result<Mapping> {
+        let socket = UdpSocket::bind(match self.gateway {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        })?;
+        socket.connect(self.gateway)?;
+
+        let request = mapping_request(opcode, external_port, self.lifetime);
+        let mut timeout = INITIAL_TIMEOUT;
+        for attempt in 1..=ATTEMPTS {
+            socket.send(request.as_slice())?;
+            socket.set_read_timeout(Some(timeout))?;
+            let mut buffer = [0; RESPONSE_LENGTH];
+            match socket.recv(&mut buffer) {
+                Ok(length) => return parse_mapping_response(&buffer[..length], opcode),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    debug!("NAT-PMP request attempt {attempt} timed out after {timeout:?}");
+                    timeout *= 2;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        warn!("NAT-PMP gateway {} did not respond", self.gateway);
+        Err(AppResponse(format!(
+            "NAT-PMP gateway {} did not respond",
+            self.gateway
+        )))
+    }
+}
+
+/// Parses the gateway address where the port defaults to 5351 when only an IP address is set
+pub fn parse_gateway(value: &str) -> Result<SocketAddr> {
+    value
+        .parse::<SocketAddr>()
+        .or_else(|_| {
+            value
+                .parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, NATPMP_PORT))
+        })
+        .map_err(|e| ParsingFailure(format!("{value} is not a valid gateway address -> {e}")))
+}
+
+fn mapping_request(opcode: u8, external_port: u16, lifetime: u32) -> Vec<u8> {
+    let mut request = vec![VERSION, opcode, 0, 0];
+    request.extend_from_slice(&INTERNAL_PORT.to_be_bytes());
+    request.extend_from_slice(&external_port.to_be_bytes());
+    request.extend_from_slice(&lifetime.to_be_bytes());
+    request
+}
+
+fn parse_mapping_response(response: &[u8], opcode: u8) -> Result<Mapping> {
+    trace!("NAT-PMP response: {:?}", response);
+    if response.len() < RESPONSE_LENGTH
+        || response[0] != VERSION
+        || response[1] != RESPONSE_OPCODE + opcode
+    {
+        return Err(ParsingFailure(format!(
+            "NAT-PMP response is not valid for opcode {opcode}: {response:?}"
+        )));
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(Mapping {
+            external_port: u16::from_be_bytes([response[10], response[11]]),
+            lifetime: u32::from_be_bytes([response[12], response[13], response[14], response[15]]),
+        }),
+        code => Err(AppResponse(format!(
+            "NAT-PMP mapping failed with result code {code}: {}",
+            match code {
+                1 => "unsupported version",
+                2 => "not authorized or refused",
+                3 => "network failure",
+                4 => "out of resources",
+                5 => "unsupported opcode",
+                _ => "unknown result code",
+            }
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Answers each request with the port, or the result code when it is not 0
+    fn gateway(
+        requests: usize,
+        port: u16,
+        result_code: u16,
+    ) -> (SocketAddr, thread::JoinHandle<Vec<Vec<u8>>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut received = Vec::new();
+            for _ in 0..requests {
+                let mut buffer = [0; 12];
+                let (length, client) = socket.recv_from(&mut buffer).unwrap();
+                let request = buffer[..length].to_vec();
+                let mut response = vec![VERSION, RESPONSE_OPCODE + request[1]];
+                response.extend_from_slice(&result_code.to_be_bytes());
+                response.extend_from_slice(&1000u32.to_be_bytes());
+                response.extend_from_slice(&request[4..6]);
+                response.extend_from_slice(&port.to_be_bytes());
+                response.extend_from_slice(&request[8..12]);
+                socket.send_to(response.as_slice(), client).unwrap();
+                received.push(request);
+            }
+            received
+        });
+        (address, handle)
+    }
+
+    fn natpmp(gateway: SocketAddr) -> NatPmp {
+        NatPmp {
+            gateway,
+            lifetime: 60,
+            port: Default::default(),
+            renew_at: Default::default(),
+        }
+    }
+
+    #[test]
+    fn forwarded_port() {
+        let (address, handle) = gateway(2, 51413, 0);
+        let source = natpmp(address);
+        assert_eq!(source.forwarded_port().unwrap(), 51413);
+        assert_eq!(
+            handle.join().unwrap(),
+            vec![
+                mapping_request(UDP_OPCODE, 0, 60),
+                mapping_request(TCP_OPCODE, 51413, 60)
+            ]
+        );
+        let renew_in = source.renew_at.get().unwrap() - Instant::now();
+        assert!(renew_in <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn gateway_address() {
+        assert_eq!(
+            parse_gateway("10.2.0.1").unwrap(),
+            "10.2.0.1:5351".parse().unwrap()
+        );
+        assert_eq!(
+            parse_gateway("10.2.0.1:5350").unwrap(),
+            "10.2.0.1:5350".parse().unwrap()
+        );
+        assert!(parse_gateway("gateway").is_err());
+    }
+
+    #[test]
+    fn mapping_refused() {
+        let (address, handle) = gateway(1, 0, 2);
+        assert!(matches!(
+            natpmp(address).forwarded_port(),
+            Err(AppResponse(_))
+        ));
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,515 @@
+use crate::apps::Protocol;
+use crate::error::Error::{AppResponse, Authorization, ParsingFailure};
+use crate::error::Result;
+use crate::sources::PortSource;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use reqwest::blocking::Client;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tracing::{debug, info, trace, warn};
+
+pub const PIA_PORT: u16 = 19999;
+const GET_SIGNATURE_ENDPOINT: &str = "/getSignature";
+const BIND_PORT_ENDPOINT: &str = "/bindPort";
+const STATUS_OK: &str = "OK";
+/// PIA releases the port when it is not bound again within 20 minutes
+const BIND_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Signed payload returned by `getSignature`, persisted so a restart keeps the same port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    pub payload: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Payload {
+    port: u16,
+    expires_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GatewayResponse {
+    status: String,
+    #[serde(default)]
+    message: String,
+    #[serde(flatten)]
+    signature: Option<Signature>,
+}
+
+/// Requests a port from the Private Internet Access gateway and keeps it bound
+pub struct Pia {
+    /// Resolves the hostname to the gateway and trusts the PIA certificate authority
+    pub client: Client,
+    pub protocol: Protocol,
+    /// Common name of the connected server, ie. `london401`
+    pub hostname: String,
+    pub port: u16,
+    pub token: String,
+    pub signature_path: PathBuf,
+    pub signature: RefCell<Option<Signature>>,
+    pub bind_at: Cell<Option<Instant>>,
+}
+
+impl PortSource for Pia {
+    fn forwarded_port(&self) -> Result<u16> {
+        let signature = self.signature()?;
+        let payload = decode_payload(signature.payload.as_str())?;
+
+        if self
+            .bind_at
+            .get()
+            .is_none_or(|bind_at| bind_at <= Instant::now())
+        {
+            self.bind_port(&signature)?;
+            self.bind_at.set(Some(Instant::now() + BIND_INTERVAL));
+            debug!("PIA port {} bound", payload.port);
+        }
+        Ok(payload.port)
+    }
+
+    fn wait(&self, interval: Duration) {
+        let now = Instant::now();
+        let wait = match self.bind_at.get() {
+            Some(bind_at) if bind_at > now => interval.min(bind_at - now),
+            _ => interval,
+        };
+        trace!("PIA waiting {:?}", wait);
+        sleep(wait);
+    }
+}
+
+impl Pia {
+    fn endpoint(&self, path: &str, params: &[(&str, &str)]) -> Result<Url> {
+        Url::parse_with_params(
+            format!(
+                "{}://{}:{}{}",
+                self.protocol, self.hostname, self.port, path
+            )
+            .as_str(),
+            params,
+        )
+        .map_err(|e| ParsingFailure(format!("PIA {path} URL is not valid -> {e}")))
+    }
+
+    /// Returns the signature in memory, or the persisted one, or requests a new one when both have expired
+    fn signature(&self) -> Result<Signature> {
+        if self.signature.borrow().is_none() {
+            self.signature.replace(self.read_signature());
+        }
+        if let Some(signature) = self.signature.borrow().as_ref()
+            && matches!(is_expired(signature), Ok(false))
+        {
+            return Ok(signature.clone());
+        }
+
+        let response =
+            self.send(self.endpoint(GET_SIGNATURE_ENDPOINT, &[("token", self.token.as_str())])?)?;
+        let signature = response
+            .signature
+            .ok_or_else(|| ParsingFailure("PIA getSignature response has no signature".into()))?;
+        let payload = decode_payload(signature.payload.as_str())?;
+        info!(
+            "PIA assigned port {} until {}",
+            payload.port, payload.expires_at
+        );
+
+        if let Some(parent) = self.signature_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(
+            self.signature_path.as_path(),
+            serde_json::to_string(&signature)
+                .map_err(|e| ParsingFailure(format!("PIA signature -> {e}")))?,
+        )?;
+        self.signature.replace(Some(signature.clone()));
+        self.bind_at.take();
+        Ok(signature)
+    }
+
+    /// Drops the signature in memory and the persisted one so the next port check requests a new signature
+    fn forget_signature(&self) {
+        self.signature.take();
+        self.bind_at.take();
+        match std::fs::remove_file(self.signature_path.as_path()) {
+            Ok(()) => debug!("Removed PIA signature {}", self.signature_path.display()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => warn!(
+                "Unable to remove PIA signature {} -> {error}",
+                self.signature_path.display()
+            ),
+        }
+    }
+
+    fn read_signature(&self) -> Option<Signature> {
+        let value = std::fs::read_to_string(self.signature_path.as_path()).ok()?;
+        match serde_json::from_str(value.as_str()) {
+            Ok(signature) => {
+                debug!(
+                    "Loaded PIA signature from {}",
+                    self.signature_path.display()
+                );
+                Some(signature)
+            }
+            Err(error) => {
+                warn!(
+                    "Ignoring PIA signature in {} -> {error}",
+                    self.signature_path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Binds the port and forgets the signature when the gateway rejects it, it is kept when the gateway can not be
+    /// reached so a network error does not give up the port
+    fn bind_port(&self, signature: &Signature) -> Result<()> {
+        let url = self.endpoint(
+            BIND_PORT_ENDPOINT,
+            &[
+                ("payload", signature.payload.as_str()),
+                ("signature", signature.signature.as_str()),
+            ],
+        )?;
+        let rejected = match self.request(url) {
+            Ok(response) => accepted(BIND_PORT_ENDPOINT, response).err(),
+            Err(error) if error.is_authorization() => Some(error),
+            Err(error) => return Err(error),
+        };
+        match rejected {
+            // A signature from another server or an expired token cannot be bound again
+            Some(error) => {
+                self.forget_signature();
+                Err(error)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn send(&self, url: Url) -> Result<GatewayResponse> {
+        let path = url.path().to_string();
+        accepted(path.as_str(), self.request(url)?)
+    }
+
+    /// Sends the request and returns the gateway response whatever its status is
+    fn request(&self, url: Url) -> Result<GatewayResponse> {
+        let path = url.path().to_string();
+        let response = self.client.get(url).send()?;
+        if response.status() == StatusCode::UNAUTHORIZED
+            || response.status() == StatusCode::FORBIDDEN
+        {
+            debug!(
+                "PIA {path} request failed with status code: {}",
+                response.status()
+            );
+            return Err(Authorization);
+        }
+        if !response.status().is_success() {
+            return Err(AppResponse(format!(
+                "PIA {path} request failed with status code: {}",
+                response.status()
+            )));
+        }
+        let response: GatewayResponse = response.json()?;
+        trace!("PIA {path} response: {:?}", response);
+        Ok(response)
+    }
+}
+
+/// Returns the response when the gateway returned status OK
+fn accepted(path: &str, response: GatewayResponse) -> Result<GatewayResponse> {
+    match response.status.as_str() {
+        STATUS_OK => Ok(response),
+        status => Err(AppResponse(format!(
+            "PIA {path} returned status {status}: {}",
+            response.message
+        ))),
+    }
+}
+
+fn decode_payload(payload: &str) -> Result<Payload> {
+    let payload = BASE64_STANDARD
+        .decode(payload)
+        .map_err(|e| ParsingFailure(format!("PIA payload is not valid base64 -> {e}")))?;
+    serde_json::from_slice(payload.as_slice())
+        .map_err(|e| ParsingFailure(format!("PIA payload is not valid json -> {e}")))
+}
+
+/// Treats the signature as expired once it would expire before the next bind
+fn is_expired(signature: &Signature) -> Result<bool> {
+    let expires_at = decode_payload(signature.payload.as_str())?.expires_at;
+    let expires_at = OffsetDateTime::parse(expires_at.as_str(), &Rfc3339)
+        .map_err(|e| ParsingFailure(format!("PIA expires_at {expires_at} is not valid -> {e}")))?;
+    Ok(expires_at <= OffsetDateTime::now_utc() + BIND_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use httpmock::prelude::GET;
+    use reqwest::Certificate;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+    use serde_json::{Value, json};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Arc;
+
+    /// Test certificate authority and the `london401` server certificate it signed
+    const TEST_CA: &[u8] = include_bytes!("pia/test_ca.pem");
+    const TEST_SERVER_CERT: &[u8] = include_bytes!("pia/test_server.pem");
+    const TEST_SERVER_KEY: &[u8] = include_bytes!("pia/test_server.key");
+    const TEST_HOSTNAME: &str = "london401";
+
+    fn payload(port: u16, expires_at: &str) -> String {
+        BASE64_STANDARD.encode(
+            json!({"token": "payloadtoken", "port": port, "expires_at": expires_at}).to_string(),
+        )
+    }
+
+    fn pia(server: &MockServer, signature_path: PathBuf) -> Pia {
+        Pia {
+            client: Default::default(),
+            protocol: Protocol::Http,
+            hostname: server.host(),
+            port: server.port(),
+            token: "sometoken".to_string(),
+            signature_path,
+            signature: Default::default(),
+            bind_at: Default::default(),
+        }
+    }
+
+    /// Answers one request per connection over TLS with the JSON response of the first matching path
+    fn https_server(connections: usize, responses: Vec<(&'static str, Value)>) -> SocketAddr {
+        let certs = CertificateDer::pem_slice_iter(TEST_SERVER_CERT)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(TEST_SERVER_KEY).unwrap();
+        let config = Arc::new(
+            ServerConfig::builder_with_provider(Arc::new(
+                rustls::crypto::aws_lc_rs::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap(),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let connection = ServerConnection::new(config.clone()).unwrap();
+                let mut stream = StreamOwned::new(connection, stream.unwrap());
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).unwrap() {
+                        0 => break,
+                        read => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let request = String::from_utf8_lossy(request.as_slice()).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let body = responses
+                    .iter()
+                    .find(|(prefix, _)| path.starts_with(prefix))
+                    .map(|(_, body)| body.to_string())
+                    .unwrap_or_default();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+                stream.conn.send_close_notify();
+                stream.flush().unwrap();
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn forwarded_port_over_https() {
+        let payload = payload(47049, "2099-01-01T00:00:00.000000000Z");
+        let address = https_server(
+            2,
+            vec![
+                (
+                    GET_SIGNATURE_ENDPOINT,
+                    json!({"status": STATUS_OK, "payload": payload, "signature": "somesignature"}),
+                ),
+                (
+                    BIND_PORT_ENDPOINT,
+                    json!({"status": STATUS_OK, "message": "port scheduled for add"}),
+                ),
+            ],
+        );
+        let signature_path =
+            std::env::temp_dir().join(format!("pia_signature_https_{}.json", std::process::id()));
+        let source = Pia {
+            client: Client::builder()
+                .resolve(TEST_HOSTNAME, address)
+                .add_root_certificate(Certificate::from_pem(TEST_CA).unwrap())
+                .build()
+                .unwrap(),
+            protocol: Protocol::Https,
+            hostname: TEST_HOSTNAME.to_string(),
+            port: address.port(),
+            token: "sometoken".to_string(),
+            signature_path: signature_path.clone(),
+            signature: Default::default(),
+            bind_at: Default::default(),
+        };
+
+        let result = source.forwarded_port();
+        std::fs::remove_file(signature_path).unwrap();
+        assert_eq!(result.unwrap(), 47049);
+    }
+
+    #[test]
+    fn rejected_token() {
+        let signature_path =
+            std::env::temp_dir().join(format!("pia_signature_token_{}.json", std::process::id()));
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path(GET_SIGNATURE_ENDPOINT);
+            then.status(401);
+        });
+        let result = pia(&server, signature_path.clone()).forwarded_port();
+        assert!(matches!(result, Err(Authorization)));
+        assert!(!signature_path.exists());
+    }
+
+    #[test]
+    fn bind_server_error_keeps_signature() {
+        let signature_path =
+            std::env::temp_dir().join(format!("pia_signature_keep_{}.json", std::process::id()));
+        std::fs::write(
+            signature_path.as_path(),
+            json!({"payload": payload(47047, "2099-01-01T00:00:00.000000000Z"), "signature": "oldsignature"})
+                .to_string(),
+        )
+        .unwrap();
+        let server = MockServer::start();
+        let bind = server.mock(|when, then| {
+            when.method(GET).path(BIND_PORT_ENDPOINT);
+            then.status(503);
+        });
+        let get_signature = server.mock(|when, then| {
+            when.method(GET).path(GET_SIGNATURE_ENDPOINT);
+            then.status(200);
+        });
+
+        let source = pia(&server, signature_path.clone());
+        let result = source.forwarded_port();
+        let kept = signature_path.exists();
+        let _ = std::fs::remove_file(signature_path);
+        assert!(matches!(result, Err(AppResponse(_))));
+        assert!(kept);
+        bind.assert();
+        get_signature.assert_calls(0);
+        assert!(source.signature.borrow().is_some());
+    }
+
+    #[test]
+    fn bind_failure_requests_new_signature() {
+        let old_payload = payload(47047, "2099-01-01T00:00:00.000000000Z");
+        let new_payload = payload(47048, "2099-01-01T00:00:00.000000000Z");
+        let signature_path =
+            std::env::temp_dir().join(format!("pia_signature_rebind_{}.json", std::process::id()));
+        std::fs::write(
+            signature_path.as_path(),
+            json!({"payload": old_payload, "signature": "oldsignature"}).to_string(),
+        )
+        .unwrap();
+        let server = MockServer::start();
+        let old_bind = server.mock(|when, then| {
+            when.method(GET)
+                .path(BIND_PORT_ENDPOINT)
+                .query_param("signature", "oldsignature");
+            then.status(200)
+                .json_body(json!({"status": "ERROR", "message": "invalid signature"}));
+        });
+        let get_signature = server.mock(|when, then| {
+            when.method(GET).path(GET_SIGNATURE_ENDPOINT);
+            then.status(200).json_body(
+                json!({"status": STATUS_OK, "payload": new_payload, "signature": "newsignature"}),
+            );
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path(BIND_PORT_ENDPOINT)
+                .query_param("signature", "newsignature");
+            then.status(200)
+                .json_body(json!({"status": STATUS_OK, "message": "port scheduled for add"}));
+        });
+
+        let source = pia(&server, signature_path.clone());
+        assert!(source.forwarded_port().is_err());
+        assert!(!signature_path.exists());
+        let result = source.forwarded_port();
+        std::fs::remove_file(signature_path).unwrap();
+        assert_eq!(result.unwrap(), 47048);
+        old_bind.assert();
+        get_signature.assert();
+    }
+
+    #[test]
+    fn forwarded_port() {
+        let payload = payload(47047, "2099-01-01T00:00:00.000000000Z");
+        let signature_path =
+            std::env::temp_dir().join(format!("pia_signature_{}.json", std::process::id()));
+        let server = MockServer::start();
+        let get_signature = server.mock(|when, then| {
+            when.method(GET)
+                .path(GET_SIGNATURE_ENDPOINT)
+                .query_param("token", "sometoken");
+            then.status(200).json_body(
+                json!({"status": STATUS_OK, "payload": payload, "signature": "somesignature"}),
+            );
+        });
+        let bind_port = server.mock(|when, then| {
+            when.method(GET)
+                .path(BIND_PORT_ENDPOINT)
+                .query_param("payload", payload.as_str())
+                .query_param("signature", "somesignature");
+            then.status(200)
+                .json_body(json!({"status": STATUS_OK, "message": "port scheduled for add"}));
+        });
+
+        let source = pia(&server, signature_path.clone());
+        assert_eq!(source.forwarded_port().unwrap(), 47047);
+        // The port is only bound again after the bind interval
+        assert_eq!(source.forwarded_port().unwrap(), 47047);
+        get_signature.assert();
+        bind_port.assert();
+
+        // A restart reuses the persisted signature
+        let source = pia(&server, signature_path.clone());
+        let result = source.forwarded_port();
+        std::fs::remove_file(signature_path).unwrap();
+        assert_eq!(result.unwrap(), 47047);
+        get_signature.assert();
+        bind_port.assert_calls(2);
+    }
+
+    #[test]
+    fn expired_signature() {
+        let signature = Signature {
+            payload: payload(47047, "2020-11-24T22:12:07.627551124Z"),
+            signature: "somesignature".to_string(),
+        };
+        assert!(is_expired(&signature).unwrap());
+    }
+}
@@ -0,0 +1,757 @@
+use crate::apps::{
+    App, BIND_ADDRESS, BIND_CHECK_ONLY, BIND_INTERFACE, DISABLE_PORT_MAPPING, InterfaceBinding,
+    PAUSE_AFTER, REANNOUNCE, REANNOUNCE_CATEGORY, REANNOUNCE_TRACKER, RECONCILE_INTERVAL,
+    ReannounceFilter, app_init, application, result_to_bool,
+};
+use crate::config::{Config, parse_var};
+use crate::error::Error::ParsingFailure;
+use crate::error::Result;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, info_span, trace, warn};
+
+// Environment Variables
+pub const TARGETS: &str = "TARGETS";
+
+const TARGET_SEPARATOR: char = ',';
+const DEFAULT_TARGET: &str = "default";
+const RECONCILE_INTERVAL_DEFAULT: u64 = 300;
+
+/// Builds the application again so credentials are re-read, ie. after a secret file was rotated
+pub type Reload = Box<dyn Fn() -> Result<Box<dyn App>>>;
+
+/// Application with its own login state so a failure only affects this target
+pub struct Target {
+    pub name: String,
+    pub app: Box<dyn App>,
+    pub reload: Option<Reload>,
+    pub logged_in: bool,
+    pub last_port: u16,
+    /// Time between reading the port back from the application, never when not set
+    pub reconcile_interval: Option<Duration>,
+    pub reconciled_at: Option<Instant>,
+    /// Torrents to reannounce after the port changed, never when not set
+    pub reannounce: Option<ReannounceFilter>,
+    /// Interface checked after each login and reconcile, never when not set
+    pub binding: Option<InterfaceBinding>,
+    /// The interface binding is in place, port updates are held while it is not
+    pub bound: bool,
+    /// UPnP and NAT-PMP are turned off with each port update
+    pub disable_port_mapping: bool,
+    pub port_mapping_reported: bool,
+    /// Time the forwarded port can be unavailable before torrents are paused, never when not set
+    pub pause_after: Option<Duration>,
+    /// Torrents paused because the forwarded port was unavailable, resumed once it is available again
+    pub paused: Option<Vec<String>>,
+    /// Time the target is checked again, every `CHECK_INTERVAL` of the application or as soon as the port changes
+    pub next_update: Instant,
+}
+
+impl Target {
+    pub fn new(name: &str, app: Box<dyn App>) -> Self {
+        Self {
+            name: name.to_string(),
+            app,
+            reload: None,
+            logged_in: false,
+            last_port: 0,
+            reconcile_interval: None,
+            reconciled_at: None,
+            reannounce: None,
+            binding: None,
+            bound: true,
+            disable_port_mapping: true,
+            port_mapping_reported: false,
+            pause_after: None,
+            paused: None,
+            next_update: Instant::now(),
+        }
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        self.next_update <= now
+    }
+
+    /// Logs in when required and sets the port when it changed since the last successful update
+    pub fn update(&mut self, port: u16) {
+        let _span = info_span!("target", name = self.name.as_str()).entered();
+        self.next_update = Instant::now() + self.app.interval();
+        if !self.logged_in {
+            if !self.login() {
+                return;
+            }
+            self.bound = self.bind_interface();
+            self.report_port_mapping();
+        } else if !self.bound || self.reconcile_interval.is_none() {
+            // Checked with each update while it is failing or when it is not checked by reconcile
+            self.bound = self.bind_interface();
+        }
+        if !self.bound {
+            warn!("Port update held until the interface binding is in place");
+            return;
+        }
+
+        if self.last_port.ne(&port) {
+            if result_to_bool(self.with_session(|app| app.set_port(port))) {
+                self.last_port = port;
+                self.reconciled_at = Some(Instant::now());
+                self.reannounce();
+            }
+        } else if self.reconcile_due() {
+            self.reconcile(port);
+        } else {
+            trace!("Current and previous port match. No update required.")
+        }
+        if self.last_port == port {
+            self.resume();
+        }
+    }
+
+    /// Pauses the torrents once the forwarded port has been unavailable for longer than `pause_after`
+    pub fn port_unavailable(&mut self, unavailable_for: Duration) {
+        let _span = info_span!("target", name = self.name.as_str()).entered();
+        self.next_update = Instant::now() + self.app.interval();
+        match self.pause_after {
+            Some(pause_after) if self.paused.is_none() && unavailable_for >= pause_after => {}
+            _ => return,
+        }
+        if !self.logged_in && !self.login() {
+            return;
+        }
+        match self.with_session(|app| app.pause()) {
+            Ok(torrents) => {
+                warn!(
+                    "Forwarded port unavailable for {} seconds, paused {} torrents",
+                    unavailable_for.as_secs(),
+                    torrents.len()
+                );
+                self.paused = Some(torrents);
+            }
+            Err(error) => error!("Unable to pause torrents -> {error}"),
+        }
+    }
+
+    /// Resumes the torrents paused while the forwarded port was unavailable
+    fn resume(&mut self) {
+        let Some(torrents) = self.paused.take() else {
+            return;
+        };
+        if torrents.is_empty() {
+            return;
+        }
+        match self.with_session(|app| app.resume(&torrents)) {
+            Ok(()) => info!("Resumed {} torrents", torrents.len()),
+            Err(error) => {
+                error!("Unable to resume torrents -> {error}");
+                self.paused = Some(torrents);
+            }
+        }
+    }
+
+    fn reconcile_due(&self) -> bool {
+        match (self.reconcile_interval, self.reconciled_at) {
+            (Some(interval), Some(reconciled_at)) => reconciled_at.elapsed() >= interval,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Reads the port back from the application and sets it again when it was changed outside of this program
+    fn reconcile(&mut self, port: u16) {
+        self.reconciled_at = Some(Instant::now());
+        self.bound = self.bind_interface();
+        if !self.bound {
+            warn!("Port update held until the interface binding is in place");
+            return;
+        }
+        match self.with_session(|app| app.current_port(port)) {
+            Ok(Some(actual_port)) if actual_port != port => {
+                warn!(
+                    "Drift detected, actual port {} does not match forwarded port {}",
+                    actual_port, port
+                );
+                if result_to_bool(self.with_session(|app| app.set_port(port))) {
+                    info!("Drift corrected from port {} to {}", actual_port, port);
+                    self.reannounce();
+                } else {
+                    // Retried on the next update like any other failed update
+                    self.last_port = 0;
+                }
+            }
+            Ok(Some(_)) => trace!("Actual port matches forwarded port {}", port),
+            Ok(None) => trace!("Actual port can not be read"),
+            Err(error) => error!("Unable to read actual port -> {error}"),
+        }
+    }
+
+    /// Sets the interface binding when it is missing, or only reports it in check only mode, and returns true when
+    /// it is in place
+    fn bind_interface(&mut self) -> bool {
+        let Some(binding) = self.binding.clone() else {
+            return true;
+        };
+        match self.with_session(|app| app.bind_interface(&binding)) {
+            Ok(()) => true,
+            Err(error) => {
+                error!("{error}");
+                false
+            }
+        }
+    }
+
+    /// Reports the UPnP and NAT-PMP settings once, they compete with the VPN forwarded port
+    fn report_port_mapping(&mut self) {
+        if self.port_mapping_reported {
+            return;
+        }
+        match self.with_session(|app| app.port_mapping()) {
+            Ok(settings) => {
+                self.port_mapping_reported = true;
+                for (name, enabled) in settings {
+                    match (enabled, self.disable_port_mapping) {
+                        (true, true) => info!("{name} is enabled and will be disabled"),
+                        (true, false) => warn!(
+                            "{name} is enabled and can interfere with the forwarded port, {DISABLE_PORT_MAPPING} is false"
+                        ),
+                        (false, _) => info!("{name} is disabled"),
+                    }
+                }
+            }
+            Err(error) => error!("Unable to read port mapping settings -> {error}"),
+        }
+    }
+
+    /// Forces an announce so trackers learn the new port before their next scheduled announce
+    fn reannounce(&mut self) {
+        if let Some(filter) = self.reannounce.clone()
+            && let Err(error) = self.with_session(|app| app.reannounce(&filter))
+        {
+            error!("Unable to reannounce -> {error}");
+        }
+    }
+
+    /// Logs in and reloads the credentials when they are rejected
+    fn login(&mut self) -> bool {
+        self.logged_in = match self.app.login() {
+            Err(error) if error.is_authorization() => {
+                error!("{error}");
+                self.reload()
+            }
+            result => result_to_bool(result),
+        };
+        self.logged_in
+    }
+
+    /// Sends the request and when the session is no longer valid logs in again and retries it once
+    fn with_session<T>(&mut self, request: impl Fn(&dyn App) -> Result<T>) -> Result<T> {
+        match request(self.app.as_ref()) {
+            Err(error) if error.is_authorization() => {
+                warn!("Logging in again -> {error}");
+                match self.login() {
+                    true => request(self.app.as_ref()),
+                    false => Err(error),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Rebuilds the application with the current credentials and logs in again
+    fn reload(&mut self) -> bool {
+        let Some(reload) = &self.reload else {
+            return false;
+        };
+        match reload() {
+            Ok(app) => {
+                debug!("Reloaded credentials");
+                self.app = app;
+                result_to_bool(self.app.login())
+            }
+            Err(error) => {
+                error!("Unable to reload credentials -> {error}");
+                false
+            }
+        }
+    }
+}
+
+/// Updates the targets that are due, or every target when the port changed so it is applied right away
+pub fn update_targets(targets: &mut [Target], port: u16, changed: bool) {
+    let now = Instant::now();
+    targets
+        .iter_mut()
+        .filter(|target| changed || target.is_due(now))
+        .for_each(|target| target.update(port))
+}
+
+/// Reports the unavailable port to the targets that are due
+pub fn port_unavailable(targets: &mut [Target], unavailable_for: Duration) {
+    let now = Instant::now();
+    targets
+        .iter_mut()
+        .filter(|target| target.is_due(now))
+        .for_each(|target| target.port_unavailable(unavailable_for))
+}
+
+/// Returns the time until the next target is due
+pub fn next_update(targets: &[Target]) -> Duration {
+    let now = Instant::now();
+    targets
+        .iter()
+        .map(|target| target.next_update.saturating_duration_since(now))
+        .min()
+        .unwrap_or_default()
+}
+
+/// Builds a target for each name in `TARGETS`, or a single target from the variables without a prefix
+pub fn targets_init(config: &Config) -> Result<Vec<Target>> {
+    let names = match config.var(TARGETS) {
+        Ok(value) => value
+            .split(TARGET_SEPARATOR)
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    if names.is_empty() {
+        return Ok(vec![target_init(config, DEFAULT_TARGET, String::new())?]);
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            let _span = info_span!("target", name = name.as_str()).entered();
+            let prefix = target_prefix(name.as_str());
+            debug!("prefix: {}", prefix);
+            target_init(config, name.as_str(), prefix)
+        })
+        .collect()
+}
+
+/// Builds the target with a reload that reads the variables and secret files again
+fn target_init(config: &Config, name: &str, prefix: String) -> Result<Target> {
+    let disable_port_mapping = match config.target_var(prefix.as_str(), DISABLE_PORT_MAPPING) {
+        Ok(value) => parse_var(DISABLE_PORT_MAPPING, value.as_str())?,
+        Err(_) => true,
+    };
+    let mut target = Target::new(
+        name,
+        app_init(config, prefix.as_str(), disable_port_mapping)?,
+    );
+    target.disable_port_mapping = disable_port_mapping;
+    let application = application(config, prefix.as_str())?;
+    let reconcile_interval = match config.target_var(prefix.as_str(), RECONCILE_INTERVAL) {
+        Ok(value) => parse_var(RECONCILE_INTERVAL, value.as_str())?,
+        Err(_) => RECONCILE_INTERVAL_DEFAULT,
+    };
+    debug!("reconcile_interval: {}", reconcile_interval);
+    target.reconcile_interval = match reconcile_interval {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    };
+    let pause_after = match config.target_var(prefix.as_str(), PAUSE_AFTER) {
+        Ok(value) => parse_var(PAUSE_AFTER, value.as_str())?,
+        Err(_) => 0,
+    };
+    debug!("pause_after: {}", pause_after);
+    if pause_after > 0 && !application.supports_pause() {
+        return Err(ParsingFailure(format!(
+            "{PAUSE_AFTER} is not supported by {application}"
+        )));
+    }
+    target.pause_after = match pause_after {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    };
+    let reannounce = match config.target_var(prefix.as_str(), REANNOUNCE) {
+        Ok(value) => parse_var(REANNOUNCE, value.as_str())?,
+        Err(_) => false,
+    };
+    if let Ok(interface) = config.target_var(prefix.as_str(), BIND_INTERFACE) {
+        if !application.supports_binding() {
+            return Err(ParsingFailure(format!(
+                "{BIND_INTERFACE} is not supported by {application}"
+            )));
+        }
+        let binding = InterfaceBinding {
+            interface,
+            address: config.target_var(prefix.as_str(), BIND_ADDRESS).ok(),
+            check_only: match config.target_var(prefix.as_str(), BIND_CHECK_ONLY) {
+                Ok(value) => parse_var(BIND_CHECK_ONLY, value.as_str())?,
+                Err(_) => false,
+            },
+        };
+        debug!("binding: {:?}", binding);
+        target.binding = Some(binding);
+    }
+    if reannounce {
+        if !application.supports_reannounce() {
+            return Err(ParsingFailure(format!(
+                "{REANNOUNCE} is not supported by {application}"
+            )));
+        }
+        let filter = ReannounceFilter {
+            category: config.target_var(prefix.as_str(), REANNOUNCE_CATEGORY).ok(),
+            tracker: config.target_var(prefix.as_str(), REANNOUNCE_TRACKER).ok(),
+        };
+        debug!("reannounce: {:?}", filter);
+        target.reannounce = Some(filter);
+    }
+    let config = config.clone();
+    target.reload = Some(Box::new(move || {
+        app_init(&config, prefix.as_str(), disable_port_mapping)
+    }));
+    Ok(target)
+}
+
+/// Returns the variable prefix for the target name, ie. `media-qbit` is `MEDIA_QBIT_`
+pub fn target_prefix(name: &str) -> String {
+    let mut prefix: String = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+    prefix.push('_');
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error::{AppResponse, Authorization, PortUpdate, Session};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    /// Records the ports it was asked to set and fails while `fail` is set
+    struct MockApp {
+        ports: Rc<RefCell<Vec<u16>>>,
+        login: bool,
+        fail: bool,
+    }
+
+    impl App for MockApp {
+        fn login(&self) -> Result<()> {
+            match self.login {
+                true => Ok(()),
+                false => Err(Authorization),
+            }
+        }
+
+        fn set_port(&self, port: u16) -> Result<()> {
+            self.ports.borrow_mut().push(port);
+            match self.fail {
+                true => Err(PortUpdate(port.to_string())),
+                false => Ok(()),
+            }
+        }
+
+        /// The last port that was set, so a port pushed by the test is drift
+        fn current_port(&self, _port: u16) -> Result<Option<u16>> {
+            Ok(self.ports.borrow().last().copied())
+        }
+
+        fn interval(&self) -> Duration {
+            Default::default()
+        }
+    }
+
+    fn target(name: &str, login: bool, fail: bool) -> (Target, Rc<RefCell<Vec<u16>>>) {
+        let ports = Rc::new(RefCell::new(Vec::new()));
+        let app = MockApp {
+            ports: ports.clone(),
+            login,
+            fail,
+        };
+        (Target::new(name, Box::new(app)), ports)
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(target_prefix("qbit"), "QBIT_");
+        assert_eq!(target_prefix("media-slskd"), "MEDIA_SLSKD_");
+    }
+
+    #[test]
+    fn unsupported_variables() {
+        let path = std::env::temp_dir().join(format!("targets_{}.toml", std::process::id()));
+        std::fs::write(
+            path.as_path(),
+            "[targets.qbit]\napplication = \"qBittorrent\"\nreannounce = true\nbind_interface = \"tun0\"\n\n\
+             [targets.transmission]\napplication = \"Transmission\"\nreannounce = true\n\n\
+             [targets.plex]\napplication = \"Plex\"\npause_after = 60\n\n\
+             [targets.slskd]\napplication = \"slskd\"\nbind_interface = \"tun0\"\n",
+        )
+        .unwrap();
+        let config = Config::load(path.as_path());
+        std::fs::remove_file(path).unwrap();
+        let config = config.unwrap();
+
+        assert!(target_init(&config, "qbit", target_prefix("qbit")).is_ok());
+        let error = target_init(&config, "transmission", target_prefix("transmission"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            ParsingFailure("REANNOUNCE is not supported by Transmission".into()).to_string()
+        );
+        let error = target_init(&config, "slskd", target_prefix("slskd"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            ParsingFailure("BIND_INTERFACE is not supported by Slskd".into()).to_string()
+        );
+        let error = target_init(&config, "plex", target_prefix("plex"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            ParsingFailure("PAUSE_AFTER is not supported by Plex".into()).to_string()
+        );
+    }
+
+    #[test]
+    fn independent_updates() {
+        let (mut ok, ok_ports) = target("ok", true, false);
+        let (mut failing, failing_ports) = target("failing", true, true);
+        let (mut logged_out, logged_out_ports) = target("logged_out", false, false);
+        for port in [51413, 51413, 51414] {
+            for target in [&mut ok, &mut failing, &mut logged_out] {
+                target.update(port);
+            }
+        }
+
+        assert_eq!(*ok_ports.borrow(), vec![51413, 51414]);
+        assert_eq!(ok.last_port, 51414);
+        // Retried on every update until it succeeds
+        assert_eq!(*failing_ports.borrow(), vec![51413, 51413, 51414]);
+        assert_eq!(failing.last_port, 0);
+        assert!(logged_out_ports.borrow().is_empty());
+        assert!(!logged_out.logged_in);
+    }
+
+    #[test]
+    fn update_due_targets() {
+        let (a, a_ports) = target("a", true, false);
+        let (b, b_ports) = target("b", true, false);
+        let mut targets = vec![a, b];
+        update_targets(&mut targets, 51413, true);
+        assert_eq!(next_update(&targets), Duration::ZERO);
+
+        targets[1].next_update = Instant::now() + Duration::from_secs(3600);
+        update_targets(&mut targets, 51414, false);
+        assert_eq!(*a_ports.borrow(), vec![51413, 51414]);
+        assert_eq!(*b_ports.borrow(), vec![51413]);
+
+        // A changed port is applied right away
+        update_targets(&mut targets, 51415, true);
+        assert_eq!(*b_ports.borrow(), vec![51413, 51415]);
+
+        targets[0].next_update = Instant::now() + Duration::from_secs(60);
+        targets[1].next_update = Instant::now() + Duration::from_secs(3600);
+        let wait = next_update(&targets);
+        assert!(wait > Duration::from_secs(50) && wait <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn correct_drift() {
+        let (mut target, ports) = target("drift", true, false);
+        target.reconcile_interval = Some(Duration::ZERO);
+        target.update(51413);
+        target.update(51413);
+        assert_eq!(*ports.borrow(), vec![51413]);
+
+        // Changed outside of this program, ie. in the application UI
+        ports.borrow_mut().push(6881);
+        target.update(51413);
+        assert_eq!(*ports.borrow(), vec![51413, 6881, 51413]);
+
+        target.reconcile_interval = None;
+        ports.borrow_mut().push(6881);
+        target.update(51413);
+        assert_eq!(*ports.borrow(), vec![51413, 6881, 51413, 6881]);
+    }
+
+    /// Session that expires when `expired` is set until the next login
+    struct SessionApp {
+        logins: Rc<Cell<u32>>,
+        expired: Rc<Cell<bool>>,
+        ports: Rc<RefCell<Vec<u16>>>,
+    }
+
+    impl App for SessionApp {
+        fn login(&self) -> Result<()> {
+            self.logins.set(self.logins.get() + 1);
+            self.expired.set(false);
+            Ok(())
+        }
+
+        fn set_port(&self, port: u16) -> Result<()> {
+            if self.expired.get() {
+                return Err(Session("expired".into()));
+            }
+            self.ports.borrow_mut().push(port);
+            Ok(())
+        }
+
+        fn interval(&self) -> Duration {
+            Default::default()
+        }
+    }
+
+    #[test]
+    fn login_again_after_session_expired() {
+        let logins = Rc::new(Cell::new(0));
+        let expired = Rc::new(Cell::new(false));
+        let ports = Rc::new(RefCell::new(Vec::new()));
+        let app = SessionApp {
+            logins: logins.clone(),
+            expired: expired.clone(),
+            ports: ports.clone(),
+        };
+        let mut target = Target::new("session", Box::new(app));
+        target.update(51413);
+        expired.set(true);
+        target.update(51414);
+
+        assert_eq!(logins.get(), 2);
+        assert!(target.logged_in);
+        assert_eq!(target.last_port, 51414);
+        assert_eq!(*ports.borrow(), vec![51413, 51414]);
+    }
+
+    #[test]
+    fn reload_after_authorization() {
+        let (mut target, old_ports) = target("rotated", false, false);
+        let new_ports = Rc::new(RefCell::new(Vec::new()));
+        let reload_ports = new_ports.clone();
+        target.reload = Some(Box::new(move || {
+            Ok(Box::new(MockApp {
+                ports: reload_ports.clone(),
+                login: true,
+                fail: false,
+            }))
+        }));
+        target.update(51413);
+
+        assert!(target.logged_in);
+        assert!(old_ports.borrow().is_empty());
+        assert_eq!(*new_ports.borrow(), vec![51413]);
+    }
+
+    /// Bound to the interface only once `bound` is set
+    struct BindApp {
+        ports: Rc<RefCell<Vec<u16>>>,
+        bound: Rc<Cell<bool>>,
+    }
+
+    impl App for BindApp {
+        fn login(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_port(&self, port: u16) -> Result<()> {
+            self.ports.borrow_mut().push(port);
+            Ok(())
+        }
+
+        fn bind_interface(&self, binding: &InterfaceBinding) -> Result<()> {
+            match self.bound.get() {
+                true => Ok(()),
+                false => Err(AppResponse(format!("Not bound to {}", binding.interface))),
+            }
+        }
+
+        fn interval(&self) -> Duration {
+            Default::default()
+        }
+    }
+
+    #[test]
+    fn hold_port_until_bound() {
+        let ports = Rc::new(RefCell::new(Vec::new()));
+        let bound = Rc::new(Cell::new(false));
+        let app = BindApp {
+            ports: ports.clone(),
+            bound: bound.clone(),
+        };
+        let mut target = Target::new("bound", Box::new(app));
+        target.binding = Some(InterfaceBinding {
+            interface: "tun0".into(),
+            address: None,
+            check_only: true,
+        });
+        target.reconcile_interval = Some(Duration::from_secs(300));
+
+        target.update(51413);
+        target.update(51413);
+        assert!(target.logged_in);
+        assert!(ports.borrow().is_empty());
+
+        bound.set(true);
+        target.update(51413);
+        assert_eq!(*ports.borrow(), vec![51413]);
+
+        // Without reconcile the binding is checked with each update
+        bound.set(false);
+        target.reconcile_interval = None;
+        target.update(51414);
+        assert_eq!(*ports.borrow(), vec![51413]);
+    }
+
+    /// Pauses the torrent "a" and records the torrents it was asked to resume
+    struct PauseApp {
+        pauses: Rc<Cell<u32>>,
+        resumed: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl App for PauseApp {
+        fn login(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_port(&self, _port: u16) -> Result<()> {
+            Ok(())
+        }
+
+        fn pause(&self) -> Result<Vec<String>> {
+            self.pauses.set(self.pauses.get() + 1);
+            Ok(vec!["a".into()])
+        }
+
+        fn resume(&self, torrents: &[String]) -> Result<()> {
+            self.resumed.borrow_mut().extend_from_slice(torrents);
+            Ok(())
+        }
+
+        fn interval(&self) -> Duration {
+            Default::default()
+        }
+    }
+
+    #[test]
+    fn pause_until_port_available() {
+        let pauses = Rc::new(Cell::new(0));
+        let resumed = Rc::new(RefCell::new(Vec::new()));
+        let app = PauseApp {
+            pauses: pauses.clone(),
+            resumed: resumed.clone(),
+        };
+        let mut target = Target::new("pause", Box::new(app));
+        target.pause_after = Some(Duration::from_secs(60));
+        target.update(51413);
+        target.port_unavailable(Duration::from_secs(30));
+        assert_eq!(pauses.get(), 0);
+
+        target.port_unavailable(Duration::from_secs(60));
+        target.port_unavailable(Duration::from_secs(90));
+        assert_eq!(pauses.get(), 1);
+        assert_eq!(target.paused, Some(vec!["a".to_string()]));
+
+        target.update(51413);
+        assert_eq!(*resumed.borrow(), vec!["a".to_string()]);
+        assert_eq!(target.paused, None);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::error::Result;
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::time::{Duration, Instant};
+use tracing::{debug, trace};
+
+/// Watches the parent directory of a file so a write is noticed without waiting for the next poll
+pub struct Watcher {
+    watcher: RefCell<RecommendedWatcher>,
+    events: Receiver<notify::Result<Event>>,
+    directory: PathBuf,
+    file: PathBuf,
+    watching: Cell<bool>,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> Result<Self> {
+        let (sender, events) = channel();
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let watcher = Self {
+            watcher: RefCell::new(notify::recommended_watcher(sender)?),
+            events,
+            directory,
+            file: path.to_path_buf(),
+            watching: Cell::new(false),
+        };
+        watcher.watch();
+        Ok(watcher)
+    }
+
+    /// Waits up to the timeout and returns true as soon as the file is written, moved into place or removed
+    pub fn wait(&self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !self.watch() {
+                // Nothing to watch until the directory exists so fall back to polling
+                std::thread::sleep(remaining);
+                return Ok(false);
+            }
+            match self.events.recv_timeout(remaining) {
+                Ok(event) => {
+                    if self.changed(event?) {
+                        return Ok(true);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(false),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(std::io::Error::other("File watcher stopped").into());
+                }
+            }
+        }
+    }
+
+    /// Watches the directory when it is not watched yet, ie. after it was created, and returns true when it is watched
+    fn watch(&self) -> bool {
+        if !self.watching.get() {
+            match self
+                .watcher
+                .borrow_mut()
+                .watch(self.directory.as_path(), RecursiveMode::NonRecursive)
+            {
+                Ok(()) => {
+                    debug!("Watching {}", self.directory.display());
+                    self.watching.set(true);
+                }
+                Err(error) => trace!("Unable to watch {} -> {error}", self.directory.display()),
+            }
+        }
+        self.watching.get()
+    }
+
+    /// Returns true when the event changed the file, or removed the directory which is then watched again
+    fn changed(&self, event: Event) -> bool {
+        trace!("watch event {:?}", event);
+        let moved_or_removed = matches!(
+            event.kind,
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+        );
+        if moved_or_removed && event.paths.contains(&self.directory) {
+            if let Err(error) = self.watcher.borrow_mut().unwatch(self.directory.as_path()) {
+                trace!("Unable to unwatch {} -> {error}", self.directory.display());
+            }
+            self.watching.set(false);
+            return true;
+        }
+        let written = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Access(AccessKind::Close(AccessMode::Write))
+        );
+        (moved_or_removed || written)
+            && event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == self.file.file_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn wait_for_write() {
+        let directory = std::env::temp_dir().join(format!("watcher_{}", std::process::id()));
+        std::fs::create_dir_all(directory.as_path()).unwrap();
+        let path = directory.join("forwarded_port");
+        let watcher = Watcher::new(path.as_path()).unwrap();
+        assert!(!watcher.wait(Duration::from_millis(50)).unwrap());
+
+        let write = |value: &'static str| {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                std::fs::write(path, value).unwrap();
+            })
+        };
+
+        // Created after the watch started
+        let start = Instant::now();
+        let handle = write("51413");
+        assert!(watcher.wait(Duration::from_secs(10)).unwrap());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        handle.join().unwrap();
+
+        // Removed and created again
+        std::fs::remove_file(path.as_path()).unwrap();
+        assert!(watcher.wait(Duration::from_secs(10)).unwrap());
+        let handle = write("51414");
+        assert!(watcher.wait(Duration::from_secs(10)).unwrap());
+        handle.join().unwrap();
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,362 @@
+use crate::error::Error::ParsingFailure;
+use crate::error::Result;
+use quick_xml::Reader;
+use quick_xml::escape::{escape, resolve_predefined_entity};
+use quick_xml::events::Event;
+use std::fmt::Write;
+
+const VALUE_TAG: &str = "value";
+const FAULT_TAG: &str = "fault";
+const FAULT_CODE: &str = "faultCode";
+const FAULT_STRING: &str = "faultString";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlRpcValue {
+    Int(i64),
+    Boolean(bool),
+    String(String),
+    Double(f64),
+    Array(Vec<XmlRpcValue>),
+    Struct(Vec<(String, XmlRpcValue)>),
+}
+
+#[derive(Debug, Clone)]
+pub struct XmlRpcRequest {
+    method: String,
+    params: Vec<XmlRpcValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlRpcResponse {
+    Success(XmlRpcValue),
+    Fault { code: i64, message: String },
+}
+
+impl XmlRpcValue {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            XmlRpcValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            XmlRpcValue::String(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&XmlRpcValue> {
+        match self {
+            XmlRpcValue::Struct(members) => members.iter().find(|(k, _)| k == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn write_xml(&self, xml: &mut String) {
+        xml.push_str("<value>");
+        match self {
+            XmlRpcValue::Int(value) => {
+                let tag = if i32::try_from(*value).is_ok() {
+                    "i4"
+                } else {
+                    "i8"
+                };
+                let _ = write!(xml, "<{tag}>{value}</{tag}>");
+            }
+            XmlRpcValue::Boolean(value) => {
+                let _ = write!(xml, "<boolean>{}</boolean>", u8::from(*value));
+            }
+            XmlRpcValue::String(value) => {
+                let _ = write!(xml, "<string>{}</string>", escape(value.as_str()));
+            }
+            XmlRpcValue::Double(value) => {
+                let _ = write!(xml, "<double>{value}</double>");
+            }
+            XmlRpcValue::Array(values) => {
+                xml.push_str("<array><data>");
+                values.iter().for_each(|v| v.write_xml(xml));
+                xml.push_str("</data></array>");
+            }
+            XmlRpcValue::Struct(members) => {
+                xml.push_str("<struct>");
+                for (name, value) in members {
+                    let _ = write!(xml, "<member><name>{}</name>", escape(name.as_str()));
+                    value.write_xml(xml);
+                    xml.push_str("</member>");
+                }
+                xml.push_str("</struct>");
+            }
+        }
+        xml.push_str("</value>");
+    }
+}
+
+impl From<&str> for XmlRpcValue {
+    fn from(value: &str) -> Self {
+        XmlRpcValue::String(value.into())
+    }
+}
+
+impl From<String> for XmlRpcValue {
+    fn from(value: String) -> Self {
+        XmlRpcValue::String(value)
+    }
+}
+
+impl From<i64> for XmlRpcValue {
+    fn from(value: i64) -> Self {
+        XmlRpcValue::Int(value)
+    }
+}
+
+impl From<bool> for XmlRpcValue {
+    fn from(value: bool) -> Self {
+        XmlRpcValue::Boolean(value)
+    }
+}
+
+impl XmlRpcRequest {
+    pub fn new<S: AsRef<str>>(method: S, params: Vec<XmlRpcValue>) -> Self {
+        Self {
+            method: method.as_ref().into(),
+            params,
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(r#"<?xml version="1.0"?><methodCall>"#);
+        let _ = write!(
+            xml,
+            "<methodName>{}</methodName><params>",
+            escape(self.method.as_str())
+        );
+        for param in &self.params {
+            xml.push_str("<param>");
+            param.write_xml(&mut xml);
+            xml.push_str("</param>");
+        }
+        xml.push_str("</params></methodCall>");
+        xml
+    }
+}
+
+impl XmlRpcResponse {
+    pub fn from_xml(xml: &str) -> Result<Self> {
+        let mut reader = Reader::from_str(xml);
+        let mut fault = false;
+        loop {
+            match reader.read_event()? {
+                Event::Start(tag) if tag.name().as_ref() == FAULT_TAG => fault = true,
+                Event::Start(tag) if tag.name().as_ref() == VALUE_TAG => break,
+                Event::Eof => {
+                    return Err(ParsingFailure(
+                        "XML-RPC response does not contain a value".into(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        let value = read_value(&mut reader)?;
+
+        if fault {
+            Ok(XmlRpcResponse::Fault {
+                code: value
+                    .get(FAULT_CODE)
+                    .and_then(|v| v.as_i64())
+                    .unwrap_or_default(),
+                message: value
+                    .get(FAULT_STRING)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        } else {
+            Ok(XmlRpcResponse::Success(value))
+        }
+    }
+}
+
+/// Reads the content of a `<value>` element whose start tag has already been consumed
+fn read_value(reader: &mut Reader<&[u8]>) -> Result<XmlRpcValue> {
+    let mut text = String::new();
+    let value = loop {
+        match reader.read_event()? {
+            Event::Text(t) => text.push_str(&t.xml10_content()),
+            Event::GeneralRef(r) => text.push_str(&resolve_reference(&r)?),
+            Event::End(tag) if tag.name().as_ref() == VALUE_TAG => {
+                // An untyped value is a string
+                return Ok(XmlRpcValue::String(text));
+            }
+            Event::Empty(tag) => {
+                break match tag.name().as_ref() {
+                    "string" => XmlRpcValue::String(String::new()),
+                    "array" => XmlRpcValue::Array(Vec::new()),
+                    "struct" => XmlRpcValue::Struct(Vec::new()),
+                    "nil" => XmlRpcValue::String(String::new()),
+                    name => {
+                        return Err(ParsingFailure(format!(
+                            "Unexpected XML-RPC element <{name}/>"
+                        )));
+                    }
+                };
+            }
+            Event::Start(tag) => {
+                let name = tag.name().as_ref().to_string();
+                break match name.as_str() {
+                    "i4" | "i8" | "int" => XmlRpcValue::Int(read_text(reader)?.trim().parse()?),
+                    "boolean" => XmlRpcValue::Boolean(read_text(reader)?.trim() == "1"),
+                    "string" => XmlRpcValue::String(read_text(reader)?),
+                    "double" => {
+                        XmlRpcValue::Double(read_text(reader)?.trim().parse().map_err(|e| {
+                            ParsingFailure(format!("XML-RPC double is not valid -> {e}"))
+                        })?)
+                    }
+                    "array" => read_array(reader)?,
+                    "struct" => read_struct(reader)?,
+                    name => {
+                        return Err(ParsingFailure(format!(
+                            "Unexpected XML-RPC element <{name}>"
+                        )));
+                    }
+                };
+            }
+            Event::Eof => return Err(ParsingFailure("XML-RPC value is incomplete".into())),
+            _ => {}
+        }
+    };
+    expect_end(reader, VALUE_TAG)?;
+    Ok(value)
+}
+
+/// Reads `<data>` values until the end of the array
+fn read_array(reader: &mut Reader<&[u8]>) -> Result<XmlRpcValue> {
+    let mut values = Vec::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(tag) if tag.name().as_ref() == VALUE_TAG => {
+                values.push(read_value(reader)?)
+            }
+            Event::End(tag) if tag.name().as_ref() == "array" => {
+                return Ok(XmlRpcValue::Array(values));
+            }
+            Event::Eof => return Err(ParsingFailure("XML-RPC array is incomplete".into())),
+            _ => {}
+        }
+    }
+}
+
+/// Reads `<member>` name and value pairs until the end of the struct
+fn read_struct(reader: &mut Reader<&[u8]>) -> Result<XmlRpcValue> {
+    let mut members = Vec::new();
+    let mut name = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(tag) if tag.name().as_ref() == "name" => name = read_text(reader)?,
+            Event::Start(tag) if tag.name().as_ref() == VALUE_TAG => {
+                members.push((std::mem::take(&mut name), read_value(reader)?))
+            }
+            Event::End(tag) if tag.name().as_ref() == "struct" => {
+                return Ok(XmlRpcValue::Struct(members));
+            }
+            Event::Eof => return Err(ParsingFailure("XML-RPC struct is incomplete".into())),
+            _ => {}
+        }
+    }
+}
+
+/// Collects text up to the end tag of the current element
+fn read_text(reader: &mut Reader<&[u8]>) -> Result<String> {
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Text(t) => text.push_str(&t.xml10_content()),
+            Event::CData(t) => text.push_str(&t.xml10_content()),
+            Event::GeneralRef(r) => text.push_str(&resolve_reference(&r)?),
+            Event::End(_) => return Ok(text),
+            Event::Eof => return Err(ParsingFailure("XML-RPC text is incomplete".into())),
+            _ => {}
+        }
+    }
+}
+
+fn expect_end(reader: &mut Reader<&[u8]>, name: &str) -> Result<()> {
+    loop {
+        match reader.read_event()? {
+            Event::End(tag) if tag.name().as_ref() == name => return Ok(()),
+            Event::Text(t) if t.trim().is_empty() => {}
+            event => {
+                return Err(ParsingFailure(format!(
+                    "Expected XML-RPC </{name}> but found {event:?}"
+                )));
+            }
+        }
+    }
+}
+
+fn resolve_reference(reference: &quick_xml::events::BytesRef) -> Result<String> {
+    if let Some(ch) = reference.resolve_char_ref()? {
+        Ok(ch.to_string())
+    } else {
+        resolve_predefined_entity(reference)
+            .map(|v| v.to_string())
+            .ok_or_else(|| ParsingFailure(format!("Unknown XML entity &{};", &**reference)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML_REQ: &str = r#"<?xml version="1.0"?><methodCall><methodName>network.port_range.set</methodName><params><param><value><string></string></value></param><param><value><string>51413-51413</string></value></param></params></methodCall>"#;
+    const XML_RESP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<methodResponse>
+<params>
+<param><value><array><data>
+<value><i8>0</i8></value>
+<value>51413-51413</value>
+<value><struct><member><name>label</name><value><string>a &amp; b</string></value></member></struct></value>
+</data></array></value></param>
+</params>
+</methodResponse>"#;
+    const XML_FAULT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<methodResponse><fault><value><struct>
+<member><name>faultCode</name><value><i4>-506</i4></value></member>
+<member><name>faultString</name><value><string>Method 'network.port' not defined</string></value></member>
+</struct></value></fault></methodResponse>"#;
+
+    #[test]
+    fn xml_rpc_request() {
+        let request = XmlRpcRequest::new(
+            "network.port_range.set",
+            vec!["".into(), "51413-51413".into()],
+        );
+        assert_eq!(request.to_xml(), XML_REQ);
+    }
+
+    #[test]
+    fn xml_rpc_response() {
+        let response = XmlRpcResponse::from_xml(XML_RESP).unwrap();
+        assert_eq!(
+            response,
+            XmlRpcResponse::Success(XmlRpcValue::Array(vec![
+                XmlRpcValue::Int(0),
+                XmlRpcValue::String("51413-51413".into()),
+                XmlRpcValue::Struct(vec![("label".into(), "a & b".into())]),
+            ]))
+        );
+    }
+
+    #[test]
+    fn xml_rpc_fault() {
+        let response = XmlRpcResponse::from_xml(XML_FAULT).unwrap();
+        assert_eq!(
+            response,
+            XmlRpcResponse::Fault {
+                code: -506,
+                message: "Method 'network.port' not defined".into()
+            }
+        );
+    }
+}